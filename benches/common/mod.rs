@@ -0,0 +1,65 @@
+//! A small benchmark harness in the spirit of Criterion, without the
+//! dependencies: each benchmark is warmed up, then timed over a number of
+//! samples, and the fastest, median and slowest time per iteration is printed.
+//!
+//! `cargo bench -- NAME` only runs the benchmarks whose name contains `NAME`.
+
+use std::env;
+use std::time::{Duration, Instant};
+
+pub use std::hint::black_box;
+
+const WARM_UP: Duration = Duration::from_millis(200);
+const SAMPLES: u32 = 20;
+const SAMPLE_TIME: Duration = Duration::from_millis(50);
+
+/// Time `f`, unless it is filtered out by the command line.
+pub fn bench<T>(name: &str, mut f: impl FnMut() -> T) {
+    let filters: Vec<String> = env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with('-'))
+        .collect();
+    if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter.as_str())) {
+        return;
+    }
+
+    // warm up, and estimate how many iterations fit in a sample
+    let start = Instant::now();
+    let mut iters: u64 = 0;
+    while start.elapsed() < WARM_UP {
+        black_box(f());
+        iters += 1;
+    }
+    let per_sample =
+        (iters * SAMPLE_TIME.as_nanos() as u64 / start.elapsed().as_nanos() as u64).max(1);
+
+    let mut samples: Vec<f64> = (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..per_sample {
+                black_box(f());
+            }
+            start.elapsed().as_nanos() as f64 / per_sample as f64
+        })
+        .collect();
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    println!(
+        "{:40} time: [{} {} {}]",
+        name,
+        fmt_ns(samples[0]),
+        fmt_ns(samples[samples.len() / 2]),
+        fmt_ns(samples[samples.len() - 1]),
+    );
+}
+
+fn fmt_ns(ns: f64) -> String {
+    if ns < 1e3 {
+        format!("{:.1} ns", ns)
+    } else if ns < 1e6 {
+        format!("{:.2} µs", ns / 1e3)
+    } else if ns < 1e9 {
+        format!("{:.2} ms", ns / 1e6)
+    } else {
+        format!("{:.2} s", ns / 1e9)
+    }
+}
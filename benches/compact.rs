@@ -0,0 +1,43 @@
+//! `State` versus `Compact`: running a 4-bit counter on 0..16 blue balls,
+//! as the solver does for each candidate program of the counter puzzle.
+
+mod common;
+
+use common::*;
+use magnus::*;
+
+fn main() {
+    let counter = State {
+        entry: [0, FALL],
+        instr: vec![
+            ijmp(0, 1, BLUE_LEVER),
+            ijmp(1, 2, BLUE_LEVER),
+            ijmp(2, 3, BLUE_LEVER),
+            ijmp(3, BLUE_LEVER, BLUE_LEVER),
+        ],
+        mem: vec![false; 4],
+        ..State::default()
+    };
+
+    bench("counter/state", || {
+        let p = black_box(&counter);
+        (0..16).all(|n| p.with_balls([n, 0]).run().register(0..4) == n as u64)
+    });
+    bench("counter/compact", || {
+        let p = black_box(&counter).to_compact().unwrap();
+        (0..16).all(|n| {
+            let mut p = p.with_balls([n, 0]);
+            p.try_run().is_ok() && p.register(0..4) == n as u64
+        })
+    });
+
+    let puzzle = Puzzle::new(
+        (0..16)
+            .map(|n| Case::new([n, 0]).expect_register(0..4, n as u64))
+            .collect(),
+    );
+    bench("counter/puzzle check", || puzzle.check(black_box(&counter)));
+    bench("counter/puzzle check_batch", || {
+        puzzle.check_batch(black_box(&counter))
+    });
+}
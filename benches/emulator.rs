@@ -0,0 +1,151 @@
+//! `State::run` on solutions of the challenges in the Turing Tumble puzzle
+//! book (see `src/bin/magnus.rs`), each run on all the cases of its challenge.
+//! The 16 runs of `ch21` are the "complete puzzle with a 16 ball input" of
+//! the README.
+
+mod common;
+
+use common::*;
+use magnus::*;
+
+const B: Addr = BLUE_LEVER;
+const R: Addr = RED_LEVER;
+
+fn main() {
+    let ch21 = State {
+        entry: [0, FALL],
+        instr: vec![ijmp(0, 1, B), ijmp(1, 2, B), ijmp(2, 3, B), ijmp(3, B, B)],
+        mem: vec![false; 4],
+        ..State::default()
+    };
+    bench("run/ch21_quantum_number", || {
+        (0..16)
+            .map(|n| black_box(&ch21).with_balls([n, 0]).run().register(0..4))
+            .sum::<u64>()
+    });
+
+    let ch22 = State {
+        balls: [8, 8],
+        entry: [0, FALL],
+        instr: vec![ijmp(0, B, 1), ijmp(1, B, 2), ijmp(2, B, 3), ijmp(3, B, B)],
+        mem: vec![false; 4],
+        ..State::default()
+    };
+    bench("run/ch22_depletion", || {
+        black_box(&ch22).clone().run().out_seq.len()
+    });
+
+    let ch27 = State {
+        balls: [8, 8],
+        entry: [0, 9],
+        instr: vec![
+            ijmp(0, 1, 5),
+            ijmp(1, 2, 2),
+            ijmp(2, 3, 3),
+            ijmp(3, 4, 4),
+            ijmp(4, B, B),
+            ijmp(5, 6, 6),
+            ijmp(6, 7, 7),
+            ijmp(7, 8, 8),
+            ijmp(8, R, R),
+            ijmp(9, INTERC0, INTERC0),
+        ],
+        mem: vec![
+            true, false, false, false, false, false, false, false, false, false,
+        ],
+        ..State::default()
+    };
+    bench("run/ch27_reflection", || {
+        (0..(1 << 9))
+            .map(|n| {
+                black_box(&ch27)
+                    .with_register(1..10, n)
+                    .run()
+                    .register(1..10)
+            })
+            .sum::<u64>()
+    });
+    bench("run/ch27_reflection batch", || {
+        (0..(1 << 9))
+            .step_by(LANES)
+            .map(|first| {
+                let mut batch = Batch::new(black_box(&ch27), LANES);
+                for lane in 0..LANES {
+                    batch.set_register(lane, 1..10, first + lane as u64);
+                }
+                let out = batch.run();
+                (0..LANES)
+                    .map(|lane| out.register(lane, 1..10))
+                    .sum::<u64>()
+            })
+            .sum::<u64>()
+    });
+
+    let ch30 = State {
+        entry: [0, B],
+        instr: vec![
+            ijmp(0, 1, B),
+            ijmp(1, 2, B),
+            ijmp(2, 3, B),
+            ijmp(3, 4, B),
+            ijmp(3, FALL, B),
+        ],
+        mem: vec![false; 4],
+        ..State::default()
+    };
+    bench("run/ch30_overflow", || {
+        (0..20)
+            .map(|n| black_box(&ch30).with_balls([n, 0]).run().register(0..4))
+            .sum::<u64>()
+    });
+
+    let ch31 = State {
+        entry: [0, FALL],
+        instr: vec![
+            ijmp(6, 1, 2),
+            ijmp(6, FALL, 6),
+            ijmp(6, 3, FALL),
+            ijmp(0, 4, B),
+            ijmp(1, 5, B),
+            ijmp(2, B, B),
+            ijmp(3, 7, B),
+            ijmp(4, 8, B),
+            ijmp(5, B, B),
+        ],
+        mem: vec![false; 7],
+        ..State::default()
+    };
+    bench("run/ch31_supervised_learning", || {
+        let p = black_box(&ch31);
+        (0..=7)
+            .flat_map(|n| [false, true].map(|switch| (n, switch)))
+            .map(|(n, switch)| {
+                p.with_bit(6, switch)
+                    .with_balls([n, 0])
+                    .run()
+                    .register(0..6)
+            })
+            .sum::<u64>()
+    });
+
+    let ch33 = State {
+        balls: [8, 8],
+        entry: [0, 5],
+        instr: vec![
+            ijmp(2, 1, 4),
+            ijmp(0, 2, 3),
+            ijmp(0, FALL, R),
+            ijmp(0, B, FALL),
+            ijmp(1, INTERC0, 6),
+            ijmp(1, 6, INTERC0),
+            ijmp(1, INTERC0, INTERC0),
+        ],
+        mem: vec![false, false, true],
+        ..State::default()
+    };
+    bench("run/ch33_teleportation", || {
+        (0..4)
+            .map(|n| black_box(&ch33).with_register(0..2, n).run().register(0..2))
+            .sum::<u64>()
+    });
+}
@@ -0,0 +1,36 @@
+//! The enumeration of candidate programs by `search_bits` (`visit_jmp01`)
+//! and `search_gear` (`visit_instr`), with a test that rejects every
+//! candidate, at 3 to 5 instructions.
+
+mod common;
+
+use common::*;
+use magnus::*;
+
+fn main() {
+    let reject = |_: &State| false;
+    for n in 3..=5 {
+        bench(&format!("enumerate/bits {}", n), || {
+            let ext_addr = [BLUE_LEVER, RED_LEVER];
+            search_bits(
+                &State::new(black_box(n)),
+                &ext_addr,
+                &Options::default(),
+                reject,
+            )
+            .tried
+        });
+    }
+    for n in 3..=5 {
+        bench(&format!("enumerate/gear {}", n), || {
+            let ext_addr = [BLUE_LEVER];
+            search_gear(
+                &State::new(black_box(n)),
+                &ext_addr,
+                &Options::default(),
+                reject,
+            )
+            .tried
+        });
+    }
+}
@@ -0,0 +1,41 @@
+//! Complete searches for the puzzles in `puzzles/`, as used in the README:
+//! `search_bits` (what `solve_bits` prints), or `search_gear` for puzzles
+//! that allow gear bits, and `search_lazy` for the others.
+
+mod common;
+
+use common::*;
+use magnus::*;
+
+fn main() {
+    let puzzles = [
+        ("counter", include_str!("../puzzles/counter.txt")),
+        ("sequence1", include_str!("../puzzles/sequence1.txt")),
+        ("set_reset", include_str!("../puzzles/set_reset.txt")),
+    ];
+    for (name, src) in puzzles {
+        let file: PuzzleFile = src.parse().unwrap();
+        let init = State::new(file.bits);
+        let opts = Options::default();
+        if file.gear {
+            bench(&format!("solve/{} search_gear", name), || {
+                search_gear(black_box(&init), &file.ext_addr, &opts, &file.puzzle)
+                    .found
+                    .len()
+            });
+        } else {
+            bench(&format!("solve/{} search_bits", name), || {
+                search_bits(black_box(&init), &file.ext_addr, &opts, &file.puzzle)
+                    .found
+                    .len()
+            });
+            bench(&format!("solve/{} search_lazy", name), || {
+                search_lazy(black_box(&init), &file.ext_addr, &opts, |t| {
+                    file.puzzle.check_partial(t)
+                })
+                .found
+                .len()
+            });
+        }
+    }
+}
@@ -33,12 +33,42 @@ pub const INTERC2: Addr = INTERC0 + 2;
 /// "A ball fell off the board".
 pub const FALL: Addr = 133;
 
+/// A jump target that has not been chosen yet (see `search_lazy`).
+/// Jumping here halts the machine and records where it happened
+/// in `State::undecided`.
+pub const UNDECIDED: Addr = 134;
+
 pub fn fmt_addr(a: Addr, f: &mut fmt::Formatter) -> fmt::Result {
     match a {
         BLUE_LEVER => f.write_str("B"),
         RED_LEVER => f.write_str("R"),
         i @ INTERC0..=INTERC2 => write!(f, "INTERC{}", i - INTERC0),
         FALL => f.write_str("FALL"),
+        UNDECIDED => f.write_str("?"),
         addr => write!(f, "{}", addr),
     }
 }
+
+/// Formats an address with `fmt_addr`, e.g. `B`.
+pub struct DisplayAddr(pub Addr);
+
+impl fmt::Display for DisplayAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_addr(self.0, f)
+    }
+}
+
+/// Inverse of `fmt_addr`: parses a numeric address or one of the
+/// symbolic names `B`, `R`, `INTERC0`-`INTERC2`, `FALL`, `?`.
+pub fn parse_addr(s: &str) -> Option<Addr> {
+    match s {
+        "B" => Some(BLUE_LEVER),
+        "R" => Some(RED_LEVER),
+        "INTERC0" => Some(INTERC0),
+        "INTERC1" => Some(INTERC1),
+        "INTERC2" => Some(INTERC2),
+        "FALL" => Some(FALL),
+        "?" => Some(UNDECIDED),
+        num => num.parse().ok(),
+    }
+}
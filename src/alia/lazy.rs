@@ -0,0 +1,167 @@
+use super::solver::*;
+use crate::*;
+use std::cell::Cell;
+
+/// A partially decided program, handed to the test function of `search_lazy`.
+pub struct Trial<'a> {
+    program: &'a State,
+    undecided: Cell<Option<(Addr, bool)>>,
+}
+
+impl<'a> Trial<'a> {
+    /// The program under test. Jumps that have not been decided yet
+    /// point to `UNDECIDED`.
+    pub fn program(&self) -> &State {
+        self.program
+    }
+
+    /// Run (a variation of) the program, like `State::run`.
+    /// If the run halts on an `UNDECIDED` jump, its `out_seq`, `mem`, ...
+    /// are only partial, and the solver will decide that jump next.
+    pub fn run(&self, p: State) -> State {
+        let p = p.run();
+        if self.undecided.get().is_none() {
+            self.undecided.set(p.undecided);
+        }
+        p
+    }
+}
+
+/// Like `search_bits`, but instead of enumerating complete programs, jump
+/// targets are only decided when a ball first reaches them. `test` runs
+/// the partially decided program through `Trial::run`, and must return false
+/// as soon as the (partial) outcome is wrong, e.g. when the first balls of the
+/// output sequence do not match. Such programs are not explored any further.
+/// Hence the search is a tree search over the code paths that are actually
+/// executed.
+///
+/// Jumps that are never reached in a solution are set to `FALL`.
+pub fn search_lazy<F>(init: &State, ext_addr: &[Addr], opts: &Options, test: F) -> Solutions
+where
+    F: Fn(&Trial) -> bool,
+{
+    let n_instr = init.instr.len() as u8;
+    let mut p = init.clone();
+    for i in 0..n_instr {
+        p.instr[i as usize] = ijmp(i, UNDECIDED, UNDECIDED);
+    }
+
+    let mut sol = Solutions::default();
+    for entry in entrypoints(n_instr, ext_addr) {
+        p.entry = entry;
+        if !visit_lazy_rec(&mut p, ext_addr, opts, &test, &mut sol) {
+            break;
+        }
+    }
+    sol
+}
+
+/// Test `p`, then recursively decide the jump where it got stuck.
+/// Returns false when the search should stop.
+fn visit_lazy_rec<F>(
+    p: &mut State,
+    ext_addr: &[Addr],
+    opts: &Options,
+    test: &F,
+    sol: &mut Solutions,
+) -> bool
+where
+    F: Fn(&Trial) -> bool,
+{
+    if !sol.count_try(opts) {
+        return false;
+    }
+    let trial = Trial {
+        program: p,
+        undecided: Cell::new(None),
+    };
+    if !test(&trial) {
+        return true;
+    }
+
+    match trial.undecided.get() {
+        None => {
+            let mut p = p.clone();
+            for instr in &mut p.instr {
+                for jmp in [&mut instr.jmp0, &mut instr.jmp1] {
+                    if *jmp == UNDECIDED {
+                        *jmp = FALL;
+                    }
+                }
+            }
+            sol.push(&p, opts)
+        }
+        Some((pc, branch)) => {
+            let n_instr = p.instr.len() as u8;
+            let targets = ((pc + 1)..n_instr).chain(ext_addr.iter().copied());
+            let mut more = true;
+            for target in targets {
+                set_jmp(p, pc, branch, target);
+                more = visit_lazy_rec(p, ext_addr, opts, test, sol);
+                if !more {
+                    break;
+                }
+            }
+            set_jmp(p, pc, branch, UNDECIDED);
+            more
+        }
+    }
+}
+
+fn set_jmp(p: &mut State, pc: Addr, branch: bool, target: Addr) {
+    let instr = &mut p.instr[pc as usize];
+    if branch {
+        instr.jmp1 = target
+    } else {
+        instr.jmp0 = target
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use Color::*;
+
+    const SEQ: [Color; 4] = [Blue, Red, Red, Blue];
+
+    fn full_test(p: &State) -> bool {
+        p.clone().run().out_seq == SEQ
+    }
+
+    fn lazy_test(t: &Trial) -> bool {
+        let out = t.run(t.program().clone());
+        if out.undecided.is_some() {
+            SEQ.starts_with(&out.out_seq)
+        } else {
+            out.out_seq == SEQ
+        }
+    }
+
+    /// Does `lazy` match `full`, up to jumps that are never taken?
+    fn covers(lazy: &State, full: &State) -> bool {
+        let same = |a, b| a == FALL || a == b;
+        lazy.entry == full.entry
+            && lazy
+                .instr
+                .iter()
+                .zip(&full.instr)
+                .all(|(l, f)| same(l.jmp0, f.jmp0) && same(l.jmp1, f.jmp1))
+    }
+
+    #[test]
+    fn lazy_finds_all_solutions() {
+        let init = State::new(3).with_balls([8, 8]);
+        let ext_addr = [BLUE_LEVER, RED_LEVER, INTERC0];
+
+        let full = search_bits(&init, &ext_addr, &Options::default(), full_test);
+        let lazy = search_lazy(&init, &ext_addr, &Options::default(), lazy_test);
+
+        assert!(!full.found.is_empty());
+        assert!(lazy.found.iter().all(full_test));
+        for f in &full.found {
+            assert_eq!(lazy.found.iter().filter(|l| covers(l, f)).count(), 1);
+        }
+        assert!(lazy.tried < full.tried);
+    }
+}
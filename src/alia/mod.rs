@@ -1,3 +1,13 @@
+mod lazy;
+mod parallel;
+mod ranked;
+mod smallest;
+mod solution_set;
 mod solver;
 
+pub use lazy::*;
+pub use parallel::*;
+pub use ranked::*;
+pub use smallest::*;
+pub use solution_set::*;
 pub use solver::*;
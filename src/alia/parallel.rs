@@ -0,0 +1,229 @@
+use super::solver::*;
+use crate::*;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+use std::sync::Mutex;
+use std::thread;
+
+/// Like `search_gear`, but spreads the search over `opts.threads` threads.
+/// Solutions are reported in the same order as `search_gear` would.
+pub fn search_gear_par<T>(init: &State, ext_addr: &[Addr], opts: &Options, test: T) -> Solutions
+where
+    T: Test + Sync,
+{
+    search_par(init, ext_addr, opts, &test, true, false)
+}
+
+/// Like `search_gear_par`, but skips the programs that `search_bits_par`
+/// visits too: those in which each instruction uses its own memory address.
+pub(super) fn search_shared_gear_par<T>(
+    init: &State,
+    ext_addr: &[Addr],
+    opts: &Options,
+    test: T,
+) -> Solutions
+where
+    T: Test + Sync,
+{
+    search_par(init, ext_addr, opts, &test, true, true)
+}
+
+/// Does an instruction use a gear bit, i.e. a memory address other than its
+/// own: that of an earlier instruction, or a memory-only gear bit?
+fn has_gear(p: &State) -> bool {
+    p.instr
+        .iter()
+        .enumerate()
+        .any(|(i, instr)| instr.mem as usize != i)
+}
+
+/// Like `search_bits`, but spreads the search over `opts.threads` threads.
+/// Solutions are reported in the same order as `search_bits` would.
+pub fn search_bits_par<T>(init: &State, ext_addr: &[Addr], opts: &Options, test: T) -> Solutions
+where
+    T: Test + Sync,
+{
+    search_par(init, ext_addr, opts, &test, false, false)
+}
+
+/// `visit_instr_rec` if gear bits are allowed, `visit_canonical_rec` with
+/// `symmetry`, `visit_jmp01_rec` otherwise.
+fn visit(
+    gear: bool,
+    symmetry: bool,
+    instr_addr: u8,
+    last: u8,
+    p: &mut State,
+    ext_addr: &[Addr],
+    f: &mut dyn FnMut(&State) -> bool,
+) -> bool {
+    if gear {
+        visit_instr_rec(instr_addr, last, p, ext_addr, f)
+    } else if symmetry {
+        visit_canonical_rec(instr_addr, last, p, ext_addr, f)
+    } else {
+        visit_jmp01_rec(instr_addr, last, p, ext_addr, f)
+    }
+}
+
+/// The search tree is cut into jobs: one per entrypoint and choice of the first
+/// (up to 2) instructions. Threads take jobs in order, and their solutions are
+/// concatenated in job order, so the result does not depend on scheduling.
+/// If `only_gear`, programs without gear bits are skipped without counting
+/// them as tried (see `has_gear`).
+fn search_par(
+    init: &State,
+    ext_addr: &[Addr],
+    opts: &Options,
+    test: &(dyn Test + Sync),
+    gear: bool,
+    only_gear: bool,
+) -> Solutions {
+    if init.instr.is_empty() {
+        if only_gear {
+            return Solutions::default();
+        }
+        // nothing to split, nor to vary
+        return search_bits(init, ext_addr, opts, |p: &State| test.test(p));
+    }
+    let n_instr = init.instr.len() as u8;
+    let split = (n_instr - 1).min(2);
+    let symmetry = opts.symmetry;
+
+    let mut jobs = vec![];
+    for entry in entrypoints(n_instr, ext_addr) {
+        let mut p = init.with_entry(entry);
+        if split == 0 {
+            jobs.push(p);
+        } else {
+            visit(gear, symmetry, 0, split - 1, &mut p, ext_addr, &mut |p| {
+                jobs.push(p.clone());
+                true
+            });
+        }
+    }
+
+    let tried = Counter::new();
+    let timed_out = AtomicBool::new(false);
+    let next_job = AtomicUsize::new(0);
+    // jobs from here on are not needed: all solutions come from earlier jobs.
+    let cutoff = AtomicUsize::new(jobs.len());
+    let done: Mutex<Vec<Option<Vec<State>>>> = Mutex::new(vec![None; jobs.len()]);
+
+    let threads = match opts.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| loop {
+                let i = next_job.fetch_add(1, Relaxed);
+                // (expired(0) always reads the clock)
+                if i >= cutoff.load(Relaxed) || timed_out.load(Relaxed) || opts.expired(0) {
+                    timed_out.fetch_or(i < cutoff.load(Relaxed), Relaxed);
+                    return;
+                }
+
+                let mut sol = Solutions::default();
+                let mut p = jobs[i].clone();
+                let mut f = |p: &State| {
+                    if only_gear && !has_gear(p) {
+                        return i < cutoff.load(Relaxed);
+                    }
+                    if !sol.count_try(opts) || timed_out.load(Relaxed) {
+                        timed_out.store(true, Relaxed);
+                        return false;
+                    }
+                    (!test.test(p) || sol.push(p, opts)) && i < cutoff.load(Relaxed)
+                };
+                visit(gear, symmetry, split, n_instr - 1, &mut p, ext_addr, &mut f);
+                // counted per job: a shared counter per candidate is contended
+                tried.add(sol.tried);
+
+                let mut done = done.lock().unwrap();
+                done[i] = Some(sol.found);
+                if let Some(max) = opts.max_solutions {
+                    let mut found = 0;
+                    for (j, job) in done.iter().enumerate() {
+                        match job {
+                            None => break,
+                            Some(sol) => found += sol.len(),
+                        }
+                        if found >= max {
+                            cutoff.fetch_min(j + 1, Relaxed);
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let mut sol = Solutions::default();
+    let cutoff = cutoff.into_inner();
+    sol.timed_out = timed_out.into_inner();
+    for found in done.into_inner().unwrap().into_iter().take(cutoff) {
+        match found {
+            Some(found) => sol.found.extend(found),
+            None if sol.timed_out => break,
+            None => panic!("job not finished"),
+        }
+    }
+    if let Some(max) = opts.max_solutions {
+        sol.found.truncate(max);
+    }
+    sol.tried = tried.get();
+    sol
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn same(a: &Solutions, b: &Solutions) -> bool {
+        let a: Vec<String> = a.found.iter().map(State::to_string).collect();
+        let b: Vec<String> = b.found.iter().map(State::to_string).collect();
+        a == b
+    }
+
+    #[test]
+    fn par_equals_serial() {
+        let test = |p: &State| p.with_balls([3, 3]).run().output_str() == "brb";
+        let ext_addr = [BLUE_LEVER, RED_LEVER, INTERC0];
+        for n in 1..=3 {
+            let init = State::new(n);
+            for opts in [
+                Options::default(),
+                Options::default().with_max_solutions(7),
+                Options::default().with_symmetry(),
+            ] {
+                let opts = opts.with_threads(4);
+                let serial = search_bits(&init, &ext_addr, &opts, test);
+                let par = search_bits_par(&init, &ext_addr, &opts, test);
+                assert!(same(&serial, &par));
+                if opts.max_solutions.is_none() {
+                    assert_eq!(serial.tried, par.tried);
+                }
+
+                let serial = search_gear(&init, &ext_addr, &opts, test);
+                let par = search_gear_par(&init, &ext_addr, &opts, test);
+                assert!(same(&serial, &par));
+            }
+        }
+    }
+
+    #[test]
+    fn shared_gear() {
+        let test = |p: &State| p.with_balls([3, 3]).run().output_str() == "brrb";
+        let ext_addr = [BLUE_LEVER, RED_LEVER];
+        let init = State::new(3);
+        let opts = Options::default().with_threads(4);
+        let gear = search_gear_par(&init, &ext_addr, &opts, test);
+        let bits = search_bits_par(&init, &ext_addr, &opts, test);
+        let shared = search_shared_gear_par(&init, &ext_addr, &opts, test);
+        assert_eq!(shared.tried + bits.tried, gear.tried);
+        assert_eq!(shared.found.len() + bits.found.len(), gear.found.len());
+        assert!(shared.found.iter().all(has_gear));
+    }
+}
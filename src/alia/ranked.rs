@@ -0,0 +1,291 @@
+use super::solver::*;
+use crate::*;
+use std::fmt;
+
+/// What makes one solution better than another: lower cost is better.
+/// Implemented by `Metric`, `Vec<Metric>` (the sum), and any
+/// `Fn(&State) -> u64`.
+pub trait Cost {
+    /// The cost of a complete program.
+    fn cost(&self, p: &State) -> u64;
+
+    /// A lower bound on `cost` for every program whose instructions
+    /// `0..n_decided` (and entrypoints) are those of `p`. The other
+    /// instructions of `p` are not decided yet, and must be ignored.
+    /// Used by `search_ranked` to skip those programs.
+    fn lower_bound(&self, _p: &State, _n_decided: usize) -> u64 {
+        0
+    }
+}
+
+impl<F> Cost for F
+where
+    F: Fn(&State) -> u64,
+{
+    fn cost(&self, p: &State) -> u64 {
+        self(p)
+    }
+}
+
+/// Built-in cost metrics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// Number of gear bits: instructions that share their memory
+    /// with another instruction.
+    GearBits,
+    /// Number of different interceptors used.
+    Interceptors,
+    /// Number of entrypoints and jumps that go to a special address
+    /// (lever, interceptor, `FALL`) rather than to an instruction.
+    SpecialTargets,
+    /// Number of balls (blue and red) released when running the program
+    /// with its own `balls`, or all of them if it fails to run (see
+    /// `State::try_run`).
+    BallsUsed,
+}
+
+impl Cost for Metric {
+    fn cost(&self, p: &State) -> u64 {
+        match self {
+            Metric::BallsUsed => match p.clone().try_run() {
+                Ok(out) => (p.balls[0] - out.balls[0]) as u64 + (p.balls[1] - out.balls[1]) as u64,
+                // never finishes: as bad as using all the balls
+                Err(_) => p.balls[0] as u64 + p.balls[1] as u64,
+            },
+            _ => self.lower_bound(p, p.instr.len()),
+        }
+    }
+
+    fn lower_bound(&self, p: &State, n_decided: usize) -> u64 {
+        let decided = &p.instr[..n_decided];
+        let targets = p
+            .entry
+            .iter()
+            .copied()
+            .chain(decided.iter().flat_map(|i| [i.jmp0, i.jmp1]));
+        match self {
+            Metric::GearBits => decided
+                .iter()
+                .filter(|i| decided.iter().filter(|j| j.mem == i.mem).count() > 1)
+                .count() as u64,
+            Metric::Interceptors => (INTERC0..=INTERC2)
+                .filter(|interc| targets.clone().any(|t| t == *interc))
+                .count() as u64,
+            Metric::SpecialTargets => {
+                targets.filter(|&t| t as usize >= p.instr.len()).count() as u64
+            }
+            Metric::BallsUsed => 0,
+        }
+    }
+}
+
+impl Cost for Vec<Metric> {
+    fn cost(&self, p: &State) -> u64 {
+        self.iter().map(|m| m.cost(p)).sum()
+    }
+
+    fn lower_bound(&self, p: &State, n_decided: usize) -> u64 {
+        self.iter().map(|m| m.lower_bound(p, n_decided)).sum()
+    }
+}
+
+/// Solutions found by `search_ranked`, cheapest first.
+#[derive(Clone, Default, Debug)]
+pub struct Ranked {
+    pub found: Vec<State>,
+
+    /// The cost of each of `found`.
+    pub cost: Vec<u64>,
+
+    /// Number of candidate programs that were tested.
+    pub tried: u64,
+
+    /// The search ran out of time (see `Options::deadline`) before it was done.
+    pub timed_out: bool,
+}
+
+/// Like `solve_bits` (or `solve_gear` if `gear` is true), but prints the
+/// solutions ranked by `cost`, cheapest first.
+pub fn solve_ranked<C, T>(init: &State, ext_addr: &[Addr], gear: bool, cost: C, test: T)
+where
+    C: Cost,
+    T: Test,
+{
+    print!(
+        "{}",
+        search_ranked(init, ext_addr, gear, &Options::default(), cost, test)
+    )
+}
+
+/// Search the solutions that pass `test`, and rank them by `cost`.
+/// Solutions of equal cost are kept in the order they were found.
+///
+/// With `opts.max_solutions`, only that many of the cheapest solutions are
+/// kept, and subtrees of the search that cannot beat them (according to
+/// `Cost::lower_bound`) are cut.
+pub fn search_ranked<C, T>(
+    init: &State,
+    ext_addr: &[Addr],
+    gear: bool,
+    opts: &Options,
+    cost: C,
+    test: T,
+) -> Ranked
+where
+    C: Cost,
+    T: Test,
+{
+    let mut search = RankedSearch {
+        ext_addr,
+        gear,
+        opts,
+        cost: &cost,
+        test: &test,
+        ranked: Ranked::default(),
+    };
+    for entry in entrypoints(init.instr.len() as u8, ext_addr) {
+        if search.ranked.timed_out {
+            break;
+        }
+        search.visit_rec(0, &mut init.with_entry(entry));
+    }
+    search.ranked
+}
+
+struct RankedSearch<'a, C, T> {
+    ext_addr: &'a [Addr],
+    gear: bool,
+    opts: &'a Options,
+    cost: &'a C,
+    test: &'a T,
+    ranked: Ranked,
+}
+
+impl<'a, C: Cost, T: Test> RankedSearch<'a, C, T> {
+    /// Vary instructions `instr_addr..`, like `visit_instr_rec` or
+    /// `visit_jmp01_rec`, skipping subtrees that cannot be ranked.
+    fn visit_rec(&mut self, instr_addr: u8, p: &mut State) {
+        if let Some(worst) = self.worst() {
+            if self.cost.lower_bound(p, instr_addr as usize) >= worst {
+                return;
+            }
+        }
+        let n_instr = p.instr.len() as u8;
+        if instr_addr == n_instr {
+            return self.visit_leaf(p);
+        }
+
+        let jmp_targets = ((instr_addr + 1)..n_instr).chain(self.ext_addr.iter().copied());
+        // the first choice is the instruction's own address
+        let n_choices = if self.gear { usize::MAX } else { 1 };
+        for mem in mem_choices(instr_addr, p).take(n_choices) {
+            for jmp0 in jmp_targets.clone() {
+                for jmp1 in jmp_targets.clone() {
+                    p.instr[instr_addr as usize] = ijmp(mem, jmp0, jmp1);
+                    self.visit_rec(instr_addr + 1, p);
+                    if self.ranked.timed_out {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    fn visit_leaf(&mut self, p: &State) {
+        let ranked = &mut self.ranked;
+        ranked.tried += 1;
+        if self.opts.expired(ranked.tried) {
+            ranked.timed_out = true;
+            return;
+        }
+        if !self.test.test(p) {
+            return;
+        }
+
+        let cost = self.cost.cost(p);
+        let i = ranked.cost.partition_point(|&c| c <= cost);
+        ranked.cost.insert(i, cost);
+        ranked.found.insert(i, p.clone());
+        if let Some(max) = self.opts.max_solutions {
+            ranked.cost.truncate(max);
+            ranked.found.truncate(max);
+        }
+    }
+
+    /// The cost that a solution must beat to be kept,
+    /// if there are already `max_solutions` solutions.
+    fn worst(&self) -> Option<u64> {
+        match self.opts.max_solutions {
+            Some(max) if self.ranked.found.len() >= max => self.ranked.cost.last().copied(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Ranked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (p, cost)) in self.found.iter().zip(&self.cost).enumerate() {
+            writeln!(
+                f,
+                "===================\nsolution{} (cost {}):\n{}===================\n",
+                i + 1,
+                cost,
+                p
+            )?;
+        }
+        if self.timed_out {
+            writeln!(f, "time limit reached")?;
+        }
+        writeln!(f, "{} candidates tried", self.tried)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn metrics() {
+        let p = State {
+            entry: [0, INTERC1],
+            instr: vec![ijmp(0, 1, INTERC0), ijmp(0, INTERC0, B), ijmp(2, B, B)],
+            mem: vec![false; 3],
+            ..State::default()
+        };
+        assert_eq!(Metric::GearBits.cost(&p), 2);
+        assert_eq!(Metric::Interceptors.cost(&p), 2);
+        assert_eq!(Metric::SpecialTargets.cost(&p), 6);
+        assert_eq!(Metric::SpecialTargets.lower_bound(&p, 1), 2);
+        assert_eq!(vec![Metric::GearBits, Metric::Interceptors].cost(&p), 4);
+
+        let counter = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, B, B)],
+            mem: vec![false; 2],
+            ..State::default()
+        };
+        assert_eq!(Metric::BallsUsed.cost(&counter.with_balls([3, 5])), 3);
+        let falls = counter.with_entry([FALL, FALL]).with_balls([3, 5]);
+        assert_eq!(Metric::BallsUsed.cost(&falls), 8);
+    }
+
+    #[test]
+    fn branch_and_bound() {
+        let init = State::new(3).with_balls([8, 8]);
+        let ext_addr = [B, R, INTERC0];
+        let test = |p: &State| p.with_balls([1, 2]).run().out_seq == [Color::Blue, Color::Red];
+        let cost = Metric::SpecialTargets;
+
+        let all = search_ranked(&init, &ext_addr, true, &Options::default(), cost, test);
+        assert!(all.cost.windows(2).all(|c| c[0] <= c[1]));
+
+        let best = search_ranked(&init, &ext_addr, true, &Options::first(), cost, test);
+        assert_eq!(best.cost, vec![all.cost[0]]);
+        assert_eq!(best.found[0].to_string(), all.found[0].to_string());
+        assert!(best.tried < all.tried);
+    }
+
+    const B: Addr = BLUE_LEVER;
+    const R: Addr = RED_LEVER;
+}
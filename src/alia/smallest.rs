@@ -0,0 +1,126 @@
+use super::parallel::*;
+use super::solver::*;
+use crate::*;
+use std::fmt;
+
+/// Result of `search_smallest`.
+#[derive(Clone, Default, Debug)]
+pub struct Smallest {
+    /// Number of instructions of the solutions, or the size that was being
+    /// searched when the search gave up.
+    pub n_instr: usize,
+
+    /// Whether the solutions use gear bits.
+    pub gear: bool,
+
+    /// Solutions of that size, and the candidates tried over all sizes.
+    pub solutions: Solutions,
+}
+
+/// Find the smallest programs that pass `test`, by iterative deepening:
+/// search programs with 0, 1, 2, ... instructions, up to `max_instr`, and
+/// stop at the first size that has solutions. If `gear` is true, gear bits
+/// are allowed too, but for each size, programs with only regular bits are
+/// tried first.
+///
+/// `opts` applies to each of these searches (`search_bits_par`,
+/// `search_gear_par`). Its deadline applies to the whole search. If no
+/// solution is found, `solutions.found` is empty, and `solutions.timed_out`
+/// tells whether the search ran out of time.
+pub fn search_smallest<T>(
+    ext_addr: &[Addr],
+    max_instr: usize,
+    gear: bool,
+    opts: &Options,
+    test: T,
+) -> Smallest
+where
+    T: Test + Sync,
+{
+    let mut result = Smallest::default();
+    let mut tried = 0;
+    for n_instr in 0..=max_instr {
+        let init = State::new(n_instr);
+        let test = |p: &State| test.test(p);
+        // with 0 or 1 instructions, there is nothing to share a gear with
+        let parts = if gear && n_instr > 1 {
+            &[false, true][..]
+        } else {
+            &[false]
+        };
+        for &gear in parts {
+            let sol = if gear {
+                // programs without gear bits were searched just before
+                search_shared_gear_par(&init, ext_addr, opts, test)
+            } else {
+                search_bits_par(&init, ext_addr, opts, test)
+            };
+            tried += sol.tried;
+            let done = !sol.found.is_empty() || sol.timed_out;
+            result = Smallest {
+                n_instr,
+                gear,
+                solutions: Solutions { tried, ..sol },
+            };
+            if done {
+                return result;
+            }
+        }
+    }
+    result
+}
+
+impl fmt::Display for Smallest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts = if self.gear { "gear bits" } else { "bits only" };
+        if self.solutions.found.is_empty() {
+            write!(f, "no solution found")?;
+        } else {
+            write!(f, "smallest solution found")?;
+        }
+        writeln!(f, ": {} instruction(s), {}", self.n_instr, parts)?;
+        write!(f, "{}", self.solutions.distinct())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn smallest_counter() {
+        let counter = Puzzle::new(
+            (0..8)
+                .map(|n| Case::new([n, 0]).expect_register(0..3, n as u64))
+                .collect(),
+        );
+        let opts = Options::first().with_threads(1);
+        let sol = search_smallest(&[BLUE_LEVER], 5, false, &opts, &counter);
+        assert_eq!((sol.n_instr, sol.gear), (3, false));
+        assert_eq!(sol.solutions.found.len(), 1);
+        assert!(sol.solutions.tried > 1);
+    }
+
+    #[test]
+    fn smallest_empty() {
+        // no instructions needed to output a blue ball
+        let blue = |p: &State| p.with_balls([1, 0]).run().out_seq == [Color::Blue];
+        let opts = Options::first().with_threads(1);
+        let sol = search_smallest(&[BLUE_LEVER], 3, true, &opts, blue);
+        assert_eq!(sol.n_instr, 0);
+        assert_eq!(sol.solutions.found[0].entry[0], BLUE_LEVER);
+    }
+
+    #[test]
+    fn time_limit() {
+        let never = |_: &State| false;
+        let opts = Options::default()
+            .with_threads(1)
+            .with_time_limit(Duration::from_millis(1));
+        let sol = search_smallest(&[BLUE_LEVER, RED_LEVER], 20, true, &opts, never);
+        assert!(sol.solutions.timed_out);
+        assert!(sol.solutions.found.is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+use super::solver::*;
+use crate::*;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Solutions, deduplicated by canonical form (see `State::canonicalize`):
+/// programs that only differ by dead code or numbering are counted once.
+/// The canonical form is only the key: it may renumber memory, so it need
+/// not pass a test that reads registers. Each class keeps the first program
+/// found for it.
+#[derive(Clone, Default, Debug)]
+pub struct SolutionSet {
+    /// The first solution found of each class, in the order they were found.
+    pub distinct: Vec<State>,
+
+    /// Number of solutions found for each of `distinct`.
+    pub count: Vec<usize>,
+
+    /// Number of candidate programs that were tested.
+    pub tried: u64,
+
+    /// The search ran out of time (see `Solutions::timed_out`).
+    pub timed_out: bool,
+
+    index: HashMap<String, usize>,
+}
+
+impl SolutionSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a solution. Returns true if it was not yet in the set.
+    pub fn insert(&mut self, p: &State) -> bool {
+        let key = p.canonicalize().to_string();
+        match self.index.get(&key) {
+            Some(&i) => {
+                self.count[i] += 1;
+                false
+            }
+            None => {
+                self.index.insert(key, self.distinct.len());
+                self.distinct.push(p.clone());
+                self.count.push(1);
+                true
+            }
+        }
+    }
+
+    /// Number of distinct solutions.
+    pub fn len(&self) -> usize {
+        self.distinct.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.distinct.is_empty()
+    }
+
+    /// The last lines of the `Display` output, e.g.
+    /// "3 distinct solutions (4000 found), 150000 candidates tried".
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        if self.timed_out {
+            summary += "time limit reached\n";
+        }
+        summary += &format!(
+            "{} distinct solutions ({} found), {} candidates tried\n",
+            self.len(),
+            self.count.iter().sum::<usize>(),
+            self.tried
+        );
+        summary
+    }
+}
+
+impl Solutions {
+    /// The distinct solutions among those found.
+    pub fn distinct(&self) -> SolutionSet {
+        let mut set = SolutionSet::new();
+        for p in &self.found {
+            set.insert(p);
+        }
+        set.tried = self.tried;
+        set.timed_out = self.timed_out;
+        set
+    }
+}
+
+impl fmt::Display for SolutionSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (p, count)) in self.distinct.iter().zip(&self.count).enumerate() {
+            writeln!(
+                f,
+                "===================\nsolution{} (found {}x):\n{}===================\n",
+                i + 1,
+                count,
+                p
+            )?;
+        }
+        write!(f, "{}", self.summary())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn dedup() {
+        let counter = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, 2, B), ijmp(2, B, B)],
+            mem: vec![false; 3],
+            ..State::default()
+        };
+        // same counter, with a dead instruction, in a different order
+        let dead_code = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 2, B), ijmp(1, R, R), ijmp(2, 3, B), ijmp(3, B, B)],
+            mem: vec![false; 4],
+            ..State::default()
+        };
+        let other = counter.with_bit(2, true);
+        let sol = Solutions {
+            found: vec![counter.clone(), dead_code, other.clone()],
+            tried: 10,
+            ..Solutions::default()
+        };
+
+        let set = sol.distinct();
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.count, vec![2, 1]);
+        assert_eq!(set.distinct[0].to_string(), counter.to_string());
+        assert_eq!(set.distinct[1].to_string(), other.to_string());
+        assert!(set
+            .to_string()
+            .ends_with("2 distinct solutions (3 found), 10 candidates tried\n"));
+    }
+
+    #[test]
+    fn canonical_forms_run_the_same() {
+        // every program with 3 instructions that runs, each in a set of its own
+        let runs = |p: &State| p.with_balls([3, 2]).try_run().ok();
+        let sol = search_bits(
+            &State::new(3),
+            &[B, R, INTERC0],
+            &Options::default(),
+            |p: &State| runs(p).is_some(),
+        );
+        assert!(sol.found.len() > 1000);
+        for p in &sol.found {
+            let want = runs(p).unwrap();
+            let got = runs(&p.canonicalize()).expect("canonical form does not run");
+            assert_eq!(got.out_seq, want.out_seq, "{}", p);
+            assert_eq!(got.intercept, want.intercept, "{}", p);
+        }
+    }
+
+    #[test]
+    fn distinct_solutions_pass() {
+        // the test reads registers, which canonical forms may renumber
+        let file: PuzzleFile = include_str!("../../puzzles/set_reset.txt").parse().unwrap();
+        let sol = file.solve(&Options::default());
+        let set = sol.distinct();
+        assert!(set.len() > 1 && set.len() < sol.found.len());
+        for p in &set.distinct {
+            assert!(file.puzzle.check(p), "{}", p);
+        }
+    }
+
+    const B: Addr = BLUE_LEVER;
+    const R: Addr = RED_LEVER;
+}
@@ -1,85 +1,315 @@
 use crate::*;
+use std::cell::Cell;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Limits on a search.
+/// By default, the entire search space is visited and all solutions are kept.
+#[derive(Clone, Default, Debug)]
+pub struct Options {
+    /// Stop searching after this many solutions have been found.
+    pub max_solutions: Option<usize>,
+
+    /// Number of worker threads used by the parallel searches
+    /// (`search_bits_par`, `search_gear_par`). 0 means one per CPU core.
+    pub threads: usize,
+
+    /// Only test one program out of each set of programs that only differ by
+    /// a renumbering of their instructions or interceptors (see `is_canonical`).
+    /// Only valid if the test does not depend on those numbers, e.g. when it
+    /// only looks at the output sequence. Programs with unreachable
+    /// instructions are skipped too: a smaller program does the same.
+    /// Only used by `search_bits(_par)`.
+    pub symmetry: bool,
+
+    /// Give up when this point in time is reached.
+    /// The solutions found so far are returned, with `timed_out` set.
+    pub deadline: Option<Instant>,
+}
+
+impl Options {
+    /// Stop at the first solution.
+    pub fn first() -> Self {
+        Self::default().with_max_solutions(1)
+    }
+
+    pub fn with_max_solutions(mut self, n: usize) -> Self {
+        self.max_solutions = Some(n);
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn with_symmetry(mut self) -> Self {
+        self.symmetry = true;
+        self
+    }
+
+    /// Give up after `limit`, counting from now.
+    pub fn with_time_limit(mut self, limit: Duration) -> Self {
+        self.deadline = Some(Instant::now() + limit);
+        self
+    }
+
+    /// Has the deadline passed? To keep this cheap, the clock is only
+    /// read once every 1024 candidates (`tried` is the count so far).
+    pub(super) fn expired(&self, tried: u64) -> bool {
+        match self.deadline {
+            Some(deadline) => tried.is_multiple_of(1024) && Instant::now() >= deadline,
+            None => false,
+        }
+    }
+}
+
+/// Programs found by a search, in the order they were found.
+#[derive(Clone, Default, Debug)]
+pub struct Solutions {
+    pub found: Vec<State>,
+
+    /// Number of candidate programs that were tested.
+    pub tried: u64,
+
+    /// The search ran out of time (see `Options::deadline`) before it was done.
+    pub timed_out: bool,
+}
+
+impl Solutions {
+    /// Record a candidate that passed the test.
+    /// Returns false when the search should stop.
+    pub(super) fn push(&mut self, p: &State, opts: &Options) -> bool {
+        self.found.push(p.clone());
+        match opts.max_solutions {
+            Some(max) => self.found.len() < max,
+            None => true,
+        }
+    }
+
+    /// Count a candidate about to be tested.
+    /// Returns false when the search ran out of time.
+    pub(super) fn count_try(&mut self, opts: &Options) -> bool {
+        self.tried += 1;
+        if opts.expired(self.tried) {
+            self.timed_out = true;
+        }
+        !self.timed_out
+    }
+
+    pub(super) fn is_full(&self, opts: &Options) -> bool {
+        self.timed_out || matches!(opts.max_solutions, Some(max) if self.found.len() >= max)
+    }
+}
+
+impl fmt::Display for Solutions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, p) in self.found.iter().enumerate() {
+            writeln!(
+                f,
+                "===================\nsolution{}:\n{}===================\n",
+                i + 1,
+                p
+            )?;
+        }
+        if self.timed_out {
+            writeln!(f, "time limit reached")?;
+        }
+        writeln!(f, "{} candidates tried", self.tried)
+    }
+}
 
 /// Solve starting from an initial board state until `test` returns true.
 /// Bits and gear bits can be used in the solution.
 /// `ext_addr` specifies which special addresses can be used. E.g.:
 ///     [BLUE_LEVER, INTERC0, INTERC1]
-pub fn solve_gear<F>(init: &State, ext_addr: &[Addr], test: F)
+/// Solutions are printed as they are found.
+pub fn solve_gear<T>(init: &State, ext_addr: &[Addr], test: T)
+where
+    T: Test,
+{
+    let sol = search_gear(init, ext_addr, &Options::default(), printing(&test));
+    print_summary(&sol);
+}
+
+/// Like `solve_gear`, but returns the solutions instead of printing them.
+///
+/// Memory past the last instruction of `init` (e.g. `State::new(4)` with a
+/// `mem` of 6 bits) holds memory-only gear bits: gear bits that no ball
+/// reaches, but that flip with the gear chain they are part of, so that
+/// `test` can read them.
+pub fn search_gear<T>(init: &State, ext_addr: &[Addr], opts: &Options, test: T) -> Solutions
 where
-    F: Fn(&State) -> bool,
+    T: Test,
 {
+    let mut sol = Solutions::default();
     for entry in entrypoints(init.instr.len() as u8, ext_addr) {
-        println!("entry {:?}", entry);
+        if sol.is_full(opts) {
+            break;
+        }
         let p = init.with_entry(entry);
         visit_instr(&p, ext_addr, |p| {
-            if test(p) {
-                println!("===================\n{}===================\n", p);
-            }
+            sol.count_try(opts) && (!test.test(p) || sol.push(p, opts))
         })
     }
+    sol
 }
 
-fn visit_instr<F>(p: &State, ext_addr: &[Addr], test: F)
+/// Visit variations on the instructions' jmp0, jmp1 and memory_address
+/// arguments, as long as `f` returns true.
+fn visit_instr<F>(p: &State, ext_addr: &[Addr], mut f: F)
 where
-    F: Fn(&State),
+    F: FnMut(&State) -> bool,
 {
-    assert!(p.instr.len() > 0);
+    if p.instr.is_empty() {
+        f(p);
+        return;
+    }
     let mut p = p.clone();
-    visit_instr_rec(0, (p.instr.len() - 1) as u8, &mut p, ext_addr, &test)
+    visit_instr_rec(0, (p.instr.len() - 1) as u8, &mut p, ext_addr, &mut f);
 }
 
-fn visit_instr_rec<F>(instr_addr: u8, max_instr: u8, p: &mut State, ext_addr: &[Addr], f: &F)
+/// Vary instructions `instr_addr..=last`, calling `f` for each variation.
+/// Returns false if `f` asked to stop.
+pub(super) fn visit_instr_rec<F>(
+    instr_addr: u8,
+    last: u8,
+    p: &mut State,
+    ext_addr: &[Addr],
+    f: &mut F,
+) -> bool
 where
-    F: Fn(&State),
+    F: FnMut(&State) -> bool + ?Sized,
 {
-    let jmp_targets = ((instr_addr + 1)..=max_instr).chain(ext_addr.iter().copied());
-
-    let mut mem_addr = [instr_addr, 255];
-    let mem_addr = if instr_addr == 0 {
-        &mem_addr[..1]
-    } else {
-        mem_addr[1] = p.instr[(instr_addr - 1) as usize].mem;
-        &mem_addr
-    };
+    let n_instr = p.instr.len() as u8;
+    let jmp_targets = ((instr_addr + 1)..n_instr).chain(ext_addr.iter().copied());
 
-    for &mem in mem_addr {
+    for mem in mem_choices(instr_addr, p) {
         for jmp0 in jmp_targets.clone() {
             for jmp1 in jmp_targets.clone() {
                 p.instr[instr_addr as usize] = ijmp(mem, jmp0, jmp1);
-                if instr_addr == max_instr {
-                    f(p);
+                let more = if instr_addr == last {
+                    f(p)
                 } else {
-                    visit_instr_rec(instr_addr + 1, max_instr, p, ext_addr, f)
+                    visit_instr_rec(instr_addr + 1, last, p, ext_addr, f)
+                };
+                if !more {
+                    return false;
                 }
             }
         }
     }
+    true
 }
 
 /// Like `solve_gear`, but not allowed to use any gear bits, only regular bits.
-pub fn solve_bits<F>(init: &State, ext_addr: &[Addr], test: F)
+pub fn solve_bits<T>(init: &State, ext_addr: &[Addr], test: T)
+where
+    T: Test,
+{
+    let sol = search_bits(init, ext_addr, &Options::default(), printing(&test));
+    print_summary(&sol);
+}
+
+/// `test`, that also prints each solution it passes, so that long searches
+/// show results right away.
+fn printing<T: Test>(test: &T) -> impl Fn(&State) -> bool + '_ {
+    let count = Cell::new(0);
+    move |p: &State| {
+        if !test.test(p) {
+            return false;
+        }
+        count.set(count.get() + 1);
+        println!(
+            "===================\nsolution{}:\n{}===================\n",
+            count.get(),
+            p
+        );
+        true
+    }
+}
+
+/// The end of the output of `solve_bits` and `solve_gear`.
+fn print_summary(sol: &Solutions) {
+    if sol.timed_out {
+        println!("time limit reached");
+    }
+    println!("{} candidates tried", sol.tried);
+}
+
+/// Like `solve_bits`, but returns the solutions instead of printing them.
+pub fn search_bits<T>(init: &State, ext_addr: &[Addr], opts: &Options, test: T) -> Solutions
 where
-    F: Fn(&State) -> bool,
+    T: Test,
 {
-    let count = Counter::new();
+    let mut sol = Solutions::default();
     for entry in entrypoints(init.instr.len() as u8, ext_addr) {
+        if sol.is_full(opts) {
+            break;
+        }
         let p = init.with_entry(entry);
-        visit_jmp01(&p, ext_addr, |p| {
-            count.inc();
-            if test(p) {
-                println!(
-                    "===================\nsolution{}:\n{}===================\n",
-                    count, p
-                );
-            }
-        })
+        let f = |p: &State| sol.count_try(opts) && (!test.test(p) || sol.push(p, opts));
+        if opts.symmetry {
+            visit_canonical(&p, ext_addr, f)
+        } else {
+            visit_jmp01(&p, ext_addr, f)
+        }
+    }
+    sol
+}
+
+/// The memory addresses that instruction `instr_addr` may use if gear bits
+/// are allowed: its own, that of any earlier instruction (joining the gear
+/// chain of that instruction), or a memory-only gear bit: an address past the
+/// last instruction (see `search_gear`).
+///
+/// Each address is offered once, so every way of chaining the gear bits is
+/// visited once: a chain of instructions only is labelled by the address of
+/// its first instruction, a chain with a memory-only gear bit by that bit.
+pub(super) fn mem_choices(instr_addr: u8, p: &State) -> MemChoices {
+    let n_instr = p.instr.len() as Addr;
+    let mut choices = MemChoices {
+        addrs: [0; BLUE_LEVER as usize],
+        len: 0,
+        next: 0,
+    };
+    // addresses already offered, as a bitset (all are below `BLUE_LEVER`)
+    let mut seen = 0u128;
+    let earlier = p.instr[..instr_addr as usize].iter().map(|i| i.mem);
+    for mem in std::iter::once(instr_addr)
+        .chain(earlier)
+        .chain(n_instr..p.mem.len() as Addr)
+    {
+        if seen >> mem & 1 == 0 {
+            seen |= 1 << mem;
+            choices.addrs[choices.len] = mem;
+            choices.len += 1;
+        }
+    }
+    choices
+}
+
+/// The addresses returned by `mem_choices`, without allocating: it is called
+/// at every node of the search tree.
+pub(super) struct MemChoices {
+    addrs: [Addr; BLUE_LEVER as usize],
+    len: usize,
+    next: usize,
+}
+
+impl Iterator for MemChoices {
+    type Item = Addr;
+
+    fn next(&mut self) -> Option<Addr> {
+        let mem = self.addrs[..self.len].get(self.next).copied();
+        self.next += 1;
+        mem
     }
-    println!("{} candidates tried", count);
 }
 
-fn entrypoints(n_instr: u8, ext_addr: &[Addr]) -> Vec<[Addr; 2]> {
-    let entry_b = (0..n_instr).into_iter().chain(ext_addr.iter().copied());
-    let entry_r = (0..n_instr).into_iter().chain(ext_addr.iter().copied());
+pub(super) fn entrypoints(n_instr: u8, ext_addr: &[Addr]) -> Vec<[Addr; 2]> {
+    let entry_b = (0..n_instr).chain(ext_addr.iter().copied());
+    let entry_r = (0..n_instr).chain(ext_addr.iter().copied());
 
     let mut result = vec![];
     for b in entry_b {
@@ -94,31 +324,113 @@ fn entrypoints(n_instr: u8, ext_addr: &[Addr]) -> Vec<[Addr; 2]> {
 
 /// Visit variations on the instructions'  jmp0, jmp1 arguments,
 /// but don't vary their memory_address argument.
-fn visit_jmp01<F>(p: &State, ext_addr: &[Addr], test: F)
+/// Stops as soon as `f` returns false.
+fn visit_jmp01<F>(p: &State, ext_addr: &[Addr], mut f: F)
+where
+    F: FnMut(&State) -> bool,
+{
+    if p.instr.is_empty() {
+        f(p);
+        return;
+    }
+    let mut p = p.clone();
+    visit_jmp01_rec(0, (p.instr.len() - 1) as u8, &mut p, ext_addr, &mut f);
+}
+
+/// Vary instructions `instr_addr..=last`, calling `f` for each variation.
+/// Returns false if `f` asked to stop.
+pub(super) fn visit_jmp01_rec<F>(
+    instr_addr: u8,
+    last: u8,
+    p: &mut State,
+    ext_addr: &[Addr],
+    f: &mut F,
+) -> bool
+where
+    F: FnMut(&State) -> bool + ?Sized,
+{
+    let n_instr = p.instr.len() as u8;
+    let jmp_targets = ((instr_addr + 1)..n_instr).chain(ext_addr.iter().copied());
+
+    for jmp0 in jmp_targets.clone() {
+        for jmp1 in jmp_targets.clone() {
+            p.instr[instr_addr as usize] = ijmp(instr_addr, jmp0, jmp1);
+            let more = if instr_addr == last {
+                f(p)
+            } else {
+                visit_jmp01_rec(instr_addr + 1, last, p, ext_addr, f)
+            };
+            if !more {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Like `visit_jmp01`, but only visits programs in canonical form
+/// (see `is_canonical`). They are generated directly: a branch is cut as
+/// soon as the instructions decided so far rule out canonical form.
+fn visit_canonical<F>(p: &State, ext_addr: &[Addr], mut f: F)
 where
-    F: Fn(&State),
+    F: FnMut(&State) -> bool,
 {
-    assert!(p.instr.len() > 0);
+    let mut f = |p: &State| {
+        debug_assert!(is_canonical(p, ext_addr), "{}", p);
+        f(p)
+    };
+    if p.instr.is_empty() {
+        if is_canonical(p, ext_addr) {
+            f(p);
+        }
+        return;
+    }
     let mut p = p.clone();
-    visit_jmp01_rec(0, (p.instr.len() - 1) as u8, &mut p, ext_addr, &test)
+    visit_canonical_rec(0, (p.instr.len() - 1) as u8, &mut p, ext_addr, &mut f);
 }
 
-fn visit_jmp01_rec<F>(instr_addr: u8, max_instr: u8, p: &mut State, ext_addr: &[Addr], f: &F)
+/// Like `visit_jmp01_rec`, but cuts the branches that cannot be canonical.
+/// Returns false if `f` asked to stop.
+pub(super) fn visit_canonical_rec<F>(
+    instr_addr: u8,
+    last: u8,
+    p: &mut State,
+    ext_addr: &[Addr],
+    f: &mut F,
+) -> bool
 where
-    F: Fn(&State),
+    F: FnMut(&State) -> bool + ?Sized,
 {
-    let jmp_targets = ((instr_addr + 1)..=max_instr).chain(ext_addr.iter().copied());
+    // Jumps only go forward, so whether this instruction is reachable
+    // is already known. If not, the program cannot be canonical.
+    let reachable = p.entry.contains(&instr_addr)
+        || p.instr[..instr_addr as usize]
+            .iter()
+            .any(|i| i.jmp0 == instr_addr || i.jmp1 == instr_addr);
+    if !reachable {
+        return true;
+    }
+
+    let n_instr = p.instr.len() as u8;
+    let jmp_targets = ((instr_addr + 1)..n_instr).chain(ext_addr.iter().copied());
 
     for jmp0 in jmp_targets.clone() {
         for jmp1 in jmp_targets.clone() {
             p.instr[instr_addr as usize] = ijmp(instr_addr, jmp0, jmp1);
-            if instr_addr == max_instr {
-                f(p);
+            if !is_canonical_prefix(p, instr_addr, ext_addr) {
+                continue;
+            }
+            let more = if instr_addr == last {
+                f(p)
             } else {
-                visit_jmp01_rec(instr_addr + 1, max_instr, p, ext_addr, f)
+                visit_canonical_rec(instr_addr + 1, last, p, ext_addr, f)
+            };
+            if !more {
+                return false;
             }
         }
     }
+    true
 }
 
 #[cfg(test)]
@@ -128,36 +440,161 @@ mod test {
 
     #[test]
     fn visit_jmp1() {
-        let mut p = State::new(1);
+        let p = State::new(1);
         let ext_addr = [BLUE_LEVER, RED_LEVER, INTERC0];
         let count = Counter::new();
-        visit_jmp01(&mut p, &ext_addr, |_| count.inc());
+        visit_jmp01(&p, &ext_addr, |_| {
+            count.inc();
+            true
+        });
         assert_eq!(count.get(), 9); // 3x3 ext_addr, no next instr
     }
 
     #[test]
     fn visit_jmp2() {
-        let mut p = State::new(2);
+        let p = State::new(2);
         let ext_addr = [BLUE_LEVER];
         let count = Counter::new();
-        visit_jmp01(&mut p, &ext_addr, |_| count.inc());
+        visit_jmp01(&p, &ext_addr, |_| {
+            count.inc();
+            true
+        });
         assert_eq!(count.get(), 4); // instr0: 11,1B,B1,BB, instr1: BB
     }
     #[test]
     fn visit_jmp3() {
-        let mut p = State::new(3);
+        let p = State::new(3);
         let ext_addr = [BLUE_LEVER];
         let count = Counter::new();
-        visit_jmp01(&mut p, &ext_addr, |_| count.inc());
+        visit_jmp01(&p, &ext_addr, |_| {
+            count.inc();
+            true
+        });
         assert_eq!(count.get(), 36); // (3!)^2
     }
 
     #[test]
     fn visit_instr2() {
-        let mut p = State::new(2);
+        let p = State::new(2);
         let ext_addr = [BLUE_LEVER];
         let count = Counter::new();
-        visit_instr(&mut p, &ext_addr, |_| count.inc());
-        assert_eq!(count.get(), 4); // instr0: 11,1B,B1,BB, instr1: BB
+        visit_instr(&p, &ext_addr, |_| {
+            count.inc();
+            true
+        });
+        assert_eq!(count.get(), 8); // instr0: 11,1B,B1,BB, instr1: BB on mem 1 or 0 (gear bit)
+    }
+
+    #[test]
+    fn gear_chains() {
+        /// All memory labellings of `p`'s instructions `instr_addr..`.
+        fn labellings(instr_addr: u8, p: &mut State, out: &mut Vec<Vec<Addr>>) {
+            if instr_addr as usize == p.instr.len() {
+                out.push(p.instr.iter().map(|i| i.mem).collect());
+                return;
+            }
+            for mem in mem_choices(instr_addr, p) {
+                p.instr[instr_addr as usize].mem = mem;
+                labellings(instr_addr + 1, p, out);
+            }
+        }
+        // (instructions, memory-only gear bits, ways to chain them)
+        for (n_instr, mem_only, count) in [(3, 0, 5), (4, 0, 15), (3, 1, 15), (3, 2, 37)] {
+            let mut p = State::new(n_instr).with_mem(vec![false; n_instr + mem_only]);
+            let mut all = vec![];
+            labellings(0, &mut p, &mut all);
+            assert_eq!(all.len(), count);
+            let mut distinct = all.clone();
+            distinct.sort();
+            distinct.dedup();
+            assert_eq!(distinct.len(), count);
+            // no two labellings chain the same instructions together
+            let mut chains: Vec<Vec<Vec<usize>>> = all
+                .iter()
+                .map(|mems| {
+                    (0..n_instr)
+                        .map(|i| (0..n_instr).filter(|&j| mems[j] == mems[i]).collect())
+                        .collect()
+                })
+                .collect();
+            chains.sort();
+            chains.dedup();
+            if mem_only == 0 {
+                assert_eq!(chains.len(), count);
+            }
+        }
+
+        // a single instruction can only set a memory-only gear bit by sharing its gear chain
+        let sets_mem1 = |p: &State| p.with_balls([1, 0]).run().mem[1];
+        let sol = search_gear(
+            &State::new(1).with_mem(vec![false; 2]),
+            &[BLUE_LEVER],
+            &Options::default(),
+            sets_mem1,
+        );
+        assert!(!sol.found.is_empty());
+        assert!(sol.found.iter().all(|p| p.instr[0].mem == 1));
+    }
+
+    fn counter(p: &State) -> bool {
+        (0..16).all(|n| p.with_balls([n, 0]).run().register(0..4) == n as u64)
+    }
+
+    #[test]
+    fn search_bits_counter() {
+        let all = search_bits(&State::new(4), &[BLUE_LEVER], &Options::default(), counter);
+        assert!(!all.found.is_empty());
+        assert!(all.found.iter().all(counter));
+
+        let first = search_bits(&State::new(4), &[BLUE_LEVER], &Options::first(), counter);
+        assert_eq!(first.found.len(), 1);
+        assert_eq!(first.found[0].to_string(), all.found[0].to_string());
+        assert!(first.tried < all.tried);
+    }
+
+    #[test]
+    fn search_max_solutions() {
+        let any = |_: &State| true;
+        let opts = Options::default().with_max_solutions(5);
+        let sol = search_gear(&State::new(2), &[BLUE_LEVER], &opts, any);
+        assert_eq!(sol.found.len(), 5);
+        assert_eq!(sol.tried, 5);
+    }
+
+    #[test]
+    fn symmetry_reduction() {
+        let ext_addr = [BLUE_LEVER, RED_LEVER, INTERC0, INTERC1];
+        for n in 1..=3 {
+            // brute force: canonical forms of all programs without unreachable instructions
+            let mut classes = std::collections::HashSet::new();
+            for entry in entrypoints(n, &ext_addr) {
+                visit_jmp01(&State::new(n as usize).with_entry(entry), &ext_addr, |p| {
+                    if canonical_order(p).len() == n as usize {
+                        classes.insert(canonical_form(p, &ext_addr).to_string());
+                    }
+                    true
+                });
+            }
+
+            // canonical programs are generated directly: every program
+            // visited is one of the classes, none is filtered out
+            let mut visited = 0;
+            for entry in entrypoints(n, &ext_addr) {
+                visit_canonical(&State::new(n as usize).with_entry(entry), &ext_addr, |p| {
+                    visited += 1;
+                    assert!(is_canonical(p, &ext_addr));
+                    true
+                });
+            }
+            assert_eq!(visited, classes.len());
+
+            let any = |_: &State| true;
+            let opts = Options::default().with_symmetry();
+            let reduced = search_bits(&State::new(n as usize), &ext_addr, &opts, any);
+            assert_eq!(reduced.found.len(), classes.len());
+            for p in &reduced.found {
+                assert!(classes.contains(&p.to_string()));
+            }
+        }
     }
 }
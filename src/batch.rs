@@ -0,0 +1,364 @@
+use super::*;
+use std::ops::Range;
+
+/// Maximum number of lanes of a `Batch`.
+pub const LANES: usize = 64;
+
+/// Runs one program on up to 64 setups (lanes) at once, e.g. the cases of a
+/// puzzle. Each lane has its own balls, start button and memory, and gives the
+/// same outcome as `State::try_run` would for that setup.
+///
+/// Memory is kept as one `u64` per address, bit `i` for lane `i`, so an
+/// instruction flips its bit for all lanes at that instruction with a single
+/// xor. Since jumps only go down the board, each ball falls through the
+/// program in one pass over the instructions, for all lanes together.
+///
+/// E.g. a 2-bit counter, run on 0..4 blue balls:
+///     let mut batch = Batch::new(&counter, 4);
+///     for n in 0..4 {
+///         batch.set_balls(n, [n as u8, 0]);
+///     }
+///     let out = batch.run();
+///     assert_eq!(out.register(3, 0..2), 3);
+#[derive(Clone, Debug)]
+pub struct Batch<'a> {
+    p: &'a State,
+    lanes: usize,
+    balls: [[u8; 2]; LANES],
+    /// lanes whose start button is red
+    start_red: u64,
+    mem: Vec<u64>,
+    /// for each ball in order: the lanes that output it, and which of those
+    /// output a red ball
+    out: Vec<[u64; 2]>,
+    /// for each interceptor: the lanes that it halted, and which of those
+    /// with a red ball
+    intercept: [[u64; 2]; 3],
+    undecided: [Option<(Addr, bool)>; LANES],
+    errors: Vec<(usize, RunError)>,
+    /// the last jump of each lane, as recorded in `State::undecided`; only
+    /// kept if an entry is `UNDECIDED`
+    from: [(Addr, bool); LANES],
+}
+
+impl<'a> Batch<'a> {
+    /// A batch of `lanes` copies of `p`, with its balls, start button and
+    /// memory, to be changed with the `set_*` methods.
+    pub fn new(p: &'a State, lanes: usize) -> Self {
+        assert!(lanes <= LANES, "at most {} lanes", LANES);
+        let all = mask(lanes);
+        Self {
+            p,
+            lanes,
+            balls: [p.balls; LANES],
+            start_red: if p.start_button == Color::Red { all } else { 0 },
+            mem: p.mem.iter().map(|&bit| if bit { all } else { 0 }).collect(),
+            out: vec![],
+            intercept: [[0; 2]; 3],
+            undecided: [None; LANES],
+            errors: vec![],
+            from: [(0, false); LANES],
+        }
+    }
+
+    pub fn lanes(&self) -> usize {
+        self.lanes
+    }
+
+    pub fn set_balls(&mut self, lane: usize, blue_red: [u8; 2]) {
+        self.balls[lane] = blue_red;
+    }
+
+    pub fn set_start(&mut self, lane: usize, start_button: Color) {
+        self.start_red &= !(1 << lane);
+        self.start_red |= ((start_button == Color::Red) as u64) << lane;
+    }
+
+    pub fn set_bit(&mut self, lane: usize, addr: Addr, value: bool) {
+        let mem = &mut self.mem[addr as usize];
+        *mem &= !(1 << lane);
+        *mem |= (value as u64) << lane;
+    }
+
+    /// Like `State::with_register`, for one lane.
+    pub fn set_register(&mut self, lane: usize, mem_range: Range<usize>, number: u64) {
+        for (i, addr) in mem_range.enumerate() {
+            self.set_bit(lane, addr as Addr, number & (1 << i) != 0);
+        }
+    }
+
+    /// Run all lanes until they halt.
+    pub fn run(mut self) -> Self {
+        let n_instr = self.p.instr.len().min(BLUE_LEVER as usize);
+        // lanes with a ball at each instruction
+        let mut at = vec![0; n_instr];
+        let mut running = mask(self.lanes);
+        let mut next_red = self.start_red;
+        let track_from = self.p.entry.contains(&UNDECIDED);
+
+        for ball in 0.. {
+            let (mut released, mut red) = (0, 0);
+            for lane in bits(running) {
+                let color = (next_red >> lane & 1) as usize;
+                if self.balls[lane][color] > 0 {
+                    self.balls[lane][color] -= 1;
+                    released |= 1 << lane;
+                    red |= (color as u64) << lane;
+                    if ball == 0 {
+                        self.from[lane] = (self.p.entry[color], false);
+                    }
+                }
+            }
+            if released == 0 {
+                break;
+            }
+
+            // lanes with a ball at each lever and interceptor
+            let mut special = [0; 5];
+            let mut jump = Jump {
+                at: &mut at,
+                special: &mut special,
+                batch: &mut self,
+                ball,
+                track_from,
+            };
+            let entry = jump.batch.p.entry;
+            jump.to(None, false, entry[0], released & !red);
+            jump.to(None, false, entry[1], red);
+            for pc in 0..n_instr {
+                let lanes = std::mem::take(&mut jump.at[pc]);
+                if lanes == 0 {
+                    continue;
+                }
+                let pc = pc as Addr;
+                let Instr { mem, jmp0, jmp1 } = jump.batch.p.instr[pc as usize];
+                let bits = match jump.batch.mem.get_mut(mem as usize) {
+                    Some(bits) => bits,
+                    None => {
+                        jump.batch
+                            .fail(lanes, RunError::MemOutOfRange { pc, mem, ball });
+                        continue;
+                    }
+                };
+                *bits ^= lanes;
+                let ones = *bits & lanes;
+                jump.to(Some(pc), true, jmp1, ones);
+                jump.to(Some(pc), false, jmp0, lanes & !ones);
+            }
+
+            let levers = special[0] | special[1];
+            self.out.push([levers, levers & red]);
+            for (i, &lanes) in special[2..].iter().enumerate() {
+                self.intercept[i][0] |= lanes;
+                self.intercept[i][1] |= lanes & red;
+            }
+            running = levers;
+            next_red = special[1];
+        }
+        self
+    }
+
+    /// The output sequence of `lane`.
+    pub fn out_seq(&self, lane: usize) -> Vec<Color> {
+        self.out_colors(lane).collect()
+    }
+
+    /// Is the output sequence of `lane` equal to `want`?
+    /// Like comparing `out_seq`, without allocating.
+    pub fn out_seq_eq(&self, lane: usize, want: &[Color]) -> bool {
+        self.out_colors(lane).eq(want.iter().copied())
+    }
+
+    fn out_colors(&self, lane: usize) -> impl Iterator<Item = Color> + '_ {
+        self.out
+            .iter()
+            .take_while(move |[lanes, _]| lanes >> lane & 1 == 1)
+            .map(move |[_, red]| color(red >> lane & 1 == 1))
+    }
+
+    /// Like `State::register`, for one lane.
+    pub fn register(&self, lane: usize, mem_range: Range<usize>) -> u64 {
+        self.mem[mem_range]
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, &bits)| acc | ((bits >> lane & 1) << i))
+    }
+
+    pub fn bit(&self, lane: usize, addr: Addr) -> bool {
+        self.mem[addr as usize] >> lane & 1 == 1
+    }
+
+    /// Balls remaining in `lane`, blue and red.
+    pub fn balls(&self, lane: usize) -> [u8; 2] {
+        self.balls[lane]
+    }
+
+    /// Like `State::intercept`, for one lane.
+    pub fn intercept(&self, lane: usize) -> [Option<Color>; 3] {
+        let mut intercept = [None; 3];
+        for (i, [lanes, red]) in self.intercept.iter().enumerate() {
+            if lanes >> lane & 1 == 1 {
+                intercept[i] = Some(color(red >> lane & 1 == 1));
+            }
+        }
+        intercept
+    }
+
+    /// Like `State::undecided`, for one lane.
+    pub fn undecided(&self, lane: usize) -> Option<(Addr, bool)> {
+        self.undecided[lane]
+    }
+
+    /// The error `State::try_run` would return for `lane`, if any.
+    pub fn error(&self, lane: usize) -> Option<&RunError> {
+        self.errors
+            .iter()
+            .find(|(l, _)| *l == lane)
+            .map(|(_, err)| err)
+    }
+
+    /// The outcome of `lane`, as `State::try_run` would return it.
+    pub fn to_state(&self, lane: usize) -> Result<State, RunError> {
+        if let Some(err) = self.error(lane) {
+            return Err(err.clone());
+        }
+        Ok(State {
+            balls: self.balls[lane],
+            start_button: color(self.start_red >> lane & 1 == 1),
+            mem: (0..self.mem.len())
+                .map(|a| self.bit(lane, a as Addr))
+                .collect(),
+            intercept: self.intercept(lane),
+            undecided: self.undecided(lane),
+            out_seq: self.out_seq(lane),
+            ..self.p.clone()
+        })
+    }
+
+    fn fail(&mut self, lanes: u64, err: RunError) {
+        for lane in bits(lanes) {
+            self.errors.push((lane, err.clone()));
+        }
+    }
+}
+
+/// Moves the balls of a set of lanes to the address they jump to.
+struct Jump<'b, 'a> {
+    at: &'b mut [u64],
+    special: &'b mut [u64; 5],
+    batch: &'b mut Batch<'a>,
+    ball: usize,
+    /// record `Batch::from` for lanes that leave the program
+    track_from: bool,
+}
+
+impl Jump<'_, '_> {
+    /// Lanes `lanes` jump to `target` from instruction `pc` (`None`: from
+    /// the top of the board), having read `value`.
+    fn to(&mut self, pc: Option<Addr>, value: bool, target: Addr, lanes: u64) {
+        if lanes == 0 {
+            return;
+        }
+        let ball = self.ball;
+        let err = match (pc, target) {
+            (Some(pc), target) if target <= pc => RunError::BackwardJump { pc, target, ball },
+            (_, target) if (target as usize) < self.at.len() => {
+                self.at[target as usize] |= lanes;
+                return;
+            }
+            (_, target) if target < BLUE_LEVER => RunError::InstrOutOfRange { pc: target, ball },
+            (_, UNDECIDED) => {
+                for lane in bits(lanes) {
+                    let from = pc.map_or(self.batch.from[lane], |pc| (pc, value));
+                    self.batch.undecided[lane] = Some(from);
+                }
+                return;
+            }
+            (_, target @ BLUE_LEVER..=INTERC2) => {
+                self.special[(target - BLUE_LEVER) as usize] |= lanes;
+                if let (Some(pc), true) = (pc, self.track_from) {
+                    for lane in bits(lanes) {
+                        self.batch.from[lane] = (pc, value);
+                    }
+                }
+                return;
+            }
+            (_, target) => RunError::FellOff { pc: target, ball },
+        };
+        self.batch.fail(lanes, err);
+    }
+}
+
+/// The first `lanes` lanes.
+fn mask(lanes: usize) -> u64 {
+    if lanes == LANES {
+        !0
+    } else {
+        (1 << lanes) - 1
+    }
+}
+
+/// The lanes in `lanes`.
+fn bits(mut lanes: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if lanes == 0 {
+            return None;
+        }
+        let lane = lanes.trailing_zeros() as usize;
+        lanes &= lanes - 1;
+        Some(lane)
+    })
+}
+
+fn color(red: bool) -> Color {
+    if red {
+        Color::Red
+    } else {
+        Color::Blue
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const B: Addr = BLUE_LEVER;
+
+    #[test]
+    fn counter() {
+        let counter = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, 2, B), ijmp(2, 3, B), ijmp(3, B, B)],
+            mem: vec![false; 4],
+            ..State::default()
+        };
+        let mut batch = Batch::new(&counter, 16);
+        for n in 0..16 {
+            batch.set_balls(n, [n as u8, 0]);
+        }
+        let out = batch.run();
+        for n in 0..16 {
+            assert_eq!(out.register(n, 0..4), n as u64);
+            assert_eq!(out.out_seq(n), vec![Color::Blue; n]);
+            assert!(out.out_seq_eq(n, &vec![Color::Blue; n]));
+            assert_eq!(out.balls(n), [0, 0]);
+        }
+    }
+
+    /// Every program with 2 instructions, on setups that cover all
+    /// outcomes, gives the same result as `try_run`.
+    #[test]
+    fn same_as_try_run() {
+        testing::same_as_try_run(|p, setups| {
+            let mut batch = Batch::new(p, setups.len());
+            for (lane, &(balls, start, mem)) in setups.iter().enumerate() {
+                batch.set_balls(lane, balls);
+                batch.set_start(lane, start);
+                batch.set_register(lane, 0..2, mem);
+            }
+            let out = batch.run();
+            (0..setups.len()).map(|lane| out.to_state(lane)).collect()
+        });
+    }
+}
@@ -1,5 +1,8 @@
 use magnus::*;
-use std::time::Instant;
+use std::env;
+use std::fs;
+use std::process::exit;
+use std::time::{Duration, Instant};
 
 const B: Addr = BLUE_LEVER;
 const R: Addr = RED_LEVER;
@@ -7,12 +10,88 @@ const I: bool = true;
 const O: bool = false;
 use Color::*;
 
+// Usage:
+//     alia [OPTIONS] PUZZLE_FILE...   solve puzzle files (see PuzzleFile)
+//     alia                            solve the built-in puzzles below
+// Options:
+//     --first              stop at the first solution
+//     --smallest           find the solutions with the fewest parts, using up
+//                          to the number of bits given in the puzzle file
+//     --time-limit SECS    give up after SECS seconds
+//     --board              lay out the solutions on the board, using the
+//                          parts of the game box
 fn main() {
+    let mut opts = Options::default();
+    let mut smallest = false;
+    let mut board = false;
+    let mut files = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--first" => opts = opts.with_max_solutions(1),
+            "--smallest" => smallest = true,
+            "--board" => board = true,
+            "--time-limit" => {
+                let secs = args.next().and_then(|s| s.parse().ok());
+                let secs: f64 = secs.unwrap_or_else(|| {
+                    eprintln!("--time-limit: expected a number of seconds");
+                    exit(1)
+                });
+                opts = opts.with_time_limit(Duration::from_secs_f64(secs));
+            }
+            _ => files.push(arg),
+        }
+    }
+
+    if files.is_empty() {
+        builtin();
+    }
+    for path in files {
+        solve_file(&path, &opts, smallest, board);
+    }
+}
+
+fn solve_file(path: &str, opts: &Options, smallest: bool, board: bool) {
+    let src = fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        exit(1)
+    });
+    let file: PuzzleFile = src.parse().unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        exit(1)
+    });
+    println!("{}", path);
+    print!("{}", file.puzzle);
+    let start = Instant::now();
+    let solutions = if smallest {
+        let found = file.solve_smallest(opts);
+        print!("{}", found);
+        found.solutions.distinct()
+    } else {
+        let found = file.solve(opts).distinct();
+        print!("{}", found);
+        found
+    };
+    println!("{}s", start.elapsed().as_secs_f32());
+
+    if board {
+        let layout_opts = LayoutOptions::default().with_inventory(Inventory::turing_tumble());
+        for (i, p) in solutions.distinct.iter().enumerate() {
+            match layout_with(p, &layout_opts) {
+                Ok(board) => print!("board{} ({}):\n{}", i, Inventory::of(&board), board),
+                Err(err) => println!("board{}: {}", i, err),
+            }
+        }
+    }
+}
+
+fn builtin() {
     time(ch32);
     time(ch21);
     time(sequence1);
     time(sequence2);
     time(ch32);
+    time(sequence3_lazy);
     time(sequence3);
 }
 
@@ -25,55 +104,66 @@ fn time<F: Fn()>(f: F) {
 fn ch32() {
     println!("ch32_set_reset");
 
-    solve_gear(&State::new(3).with_balls([1, 1]), &[B, R], |p| -> bool {
-        for flipflop_state in [O, I] {
-            if p.with_start(Blue).with_bit(0, flipflop_state).run().bit(0) != false {
-                return false;
-            }
-            if p.with_start(Red).with_bit(0, flipflop_state).run().bit(0) != true {
-                return false;
-            }
-        }
-        true
-    });
+    let mut cases = vec![];
+    for flipflop_state in [O, I] {
+        let case = Case::new([1, 1]).with_bit(0, flipflop_state);
+        cases.push(case.clone().with_start(Blue).expect_bit(0, O));
+        cases.push(case.with_start(Red).expect_bit(0, I));
+    }
+    let puzzle = Puzzle::new(cases);
+    print!("{}", puzzle);
+
+    // fewest gear bits first
+    solve_ranked(&State::new(3), &[B, R], true, Metric::GearBits, puzzle);
 }
 
 fn sequence1() {
-    solve_bits(&State::new(4).with_balls([8, 8]), &[B, R, INTERC0], |p| {
-        p.clone()
-            .run()
-            .out_seq
-            .eq(&[Blue, Red, Red, Blue, Blue, Blue])
-    })
+    let puzzle = Puzzle::new(vec![
+        Case::new([8, 8]).expect_out(&[Blue, Red, Red, Blue, Blue, Blue])
+    ]);
+    solve_bits(&State::new(4), &[B, R, INTERC0], puzzle)
 }
 
 fn sequence2() {
-    solve_bits(&State::new(4).with_balls([4, 6]), &[B, R], |p| {
-        p.clone()
-            .run()
-            .out_seq
-            .eq(&[Blue, Red, Red, Blue, Blue, Blue, Red, Red, Red, Red])
-    })
+    let puzzle = Puzzle::new(vec![
+        Case::new([4, 6]).expect_out(&[Blue, Red, Red, Blue, Blue, Blue, Red, Red, Red, Red])
+    ]);
+    solve_bits(&State::new(4), &[B, R], puzzle)
+}
+
+fn sequence3_puzzle() -> Puzzle {
+    Puzzle::new(vec![Case::new([9, 6]).expect_out(&[
+        Blue, Red, Red, Blue, Blue, Blue, Red, Red, Red, Red, Blue, Blue, Blue, Blue, Blue,
+    ])])
 }
 
 fn sequence3() {
-    solve_bits(&State::new(6).with_balls([9, 6]), &[B, R], |p| {
-        p.clone().run().out_seq.eq(&[
-            Blue, Red, Red, Blue, Blue, Blue, Red, Red, Red, Red, Blue, Blue, Blue, Blue, Blue,
-        ])
-    })
+    let solutions = search_bits_par(
+        &State::new(6),
+        &[B, R],
+        &Options::default().with_symmetry(),
+        sequence3_puzzle(),
+    );
+    print!("{}", solutions.distinct());
+}
+
+// Like sequence3, but rejects programs as soon as the first balls mismatch.
+fn sequence3_lazy() {
+    let puzzle = sequence3_puzzle();
+    let solutions = search_lazy(&State::new(6), &[B, R], &Options::default(), |t| {
+        puzzle.check_partial(t)
+    });
+    print!("{}", solutions.distinct());
 }
 
 fn ch21() {
     // 4-bit counter
     println!("ch21_quantum_number");
 
-    solve_bits(&State::new(4), &[B], |p| {
-        for n in 0..16 {
-            if p.with_balls([n, 0]).run_verbosity(0).register(0..4) != n as u64 {
-                return false;
-            }
-        }
-        true
-    })
+    let puzzle = Puzzle::new(
+        (0..16)
+            .map(|n| Case::new([n, 0]).expect_register(0..4, n as u64))
+            .collect(),
+    );
+    solve_bits(&State::new(4), &[B], puzzle)
 }
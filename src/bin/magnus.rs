@@ -48,13 +48,26 @@ fn default() -> State {
     State::default()
 }
 
+/// Check a hand-written program before running it (see `State::validate`),
+/// panicking with its errors if it has any. Warnings are ignored.
+fn checked(p: State) -> State {
+    let errors: Vec<String> = p
+        .validate()
+        .iter()
+        .filter(|diag| diag.is_error())
+        .map(Diagnostic::to_string)
+        .collect();
+    assert!(errors.is_empty(), "invalid program:\n{}", errors.join("\n"));
+    p
+}
+
 fn ch1() {
     println!("ch1_gravity");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [B, 0],
         ..default()
-    }
+    })
     .run();
 
     assert_eq!(&result.output_str(), "bbbbbbbb");
@@ -62,11 +75,11 @@ fn ch1() {
 
 fn ch2() {
     println!("ch1_reentry");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [B, 0],
         ..default()
-    }
+    })
     .run();
 
     assert_eq!(&result.output_str(), "bbbbbbbb");
@@ -74,11 +87,11 @@ fn ch2() {
 
 fn ch3() {
     println!("ch3_ignition");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [R, R],
         ..default()
-    }
+    })
     .run();
 
     assert_eq!(&result.output_str(), "brrrrrrrr");
@@ -86,25 +99,24 @@ fn ch3() {
 
 fn ch4() {
     println!("ch4_fusion");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [B, B],
         start_button: Red,
         ..default()
-    }
+    })
     .run();
 
     assert_eq!(&result.output_str(), "rbbbbbbbb");
 }
 
-
 fn ch5() {
     println!("ch5_entropy");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [R, B],
         ..default()
-    }
+    })
     .run();
 
     assert_eq!(&result.output_str(), "brbrbrbrbrbrbrbr");
@@ -113,11 +125,11 @@ fn ch5() {
 fn ch6() {
     // equivalent to challenge 5 up to routing
     println!("ch6_total_internal_reflection");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [R, B],
         ..default()
-    }
+    })
     .run();
 
     assert_eq!(&result.output_str(), "brbrbrbrbrbrbrbr");
@@ -126,37 +138,37 @@ fn ch6() {
 fn ch7() {
     // equivalent to challenge 1 up to routing
     println!("ch7_path_of_least_resistance");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [B, 0],
         ..default()
-    }
+    })
     .run();
     assert_eq!(&result.output_str(), "bbbbbbbb");
 }
 
 fn ch8() {
     println!("ch8_depolarization");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [0, 0],
         instr: vec![ijmp(0, B, R)],
         mem: vec![O],
         ..default()
-    }
+    })
     .run();
     assert_eq!(&result.output_str(), "brbrbrbrbrbrbrbr");
 }
 
 fn ch9() {
     println!("ch9_dimers");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [0, B],
         instr: vec![ijmp(0, B, R)],
         mem: vec![I],
         ..default()
-    }
+    })
     .run();
     assert_eq!(&result.output_str(), "bbrbbrbbrbbr");
 }
@@ -166,7 +178,7 @@ fn ch10() {
     // bit0 jmp0 has to be B, regardless the rest of the program.
     // I.e. the ability to reject a partial program based on partial output.
     println!("ch10_double_bond");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [0, 1],
         instr: vec![
@@ -175,14 +187,14 @@ fn ch10() {
         ],
         mem: vec![I, O],
         ..default()
-    }
+    })
     .run();
     assert_eq!(&result.output_str(), "bbrrbbrrbbrrbbrr");
 }
 
 fn ch11() {
     println!("ch11_selectivity");
-    let result = State {
+    let result = checked(State {
         balls: [2, 0],
         entry: [0, FALL],
         instr: vec![
@@ -195,14 +207,14 @@ fn ch11() {
         ],
         mem: vec![I, O, O, O, O, O],
         ..default()
-    }
+    })
     .run();
     assert_eq!(&result.mem_str()[1..], "01001");
 }
 
 fn ch12() {
     println!("ch12_duality_part1");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [0, FALL],
         instr: vec![
@@ -210,14 +222,14 @@ fn ch12() {
         ],
         mem: vec![O],
         ..default()
-    }
+    })
     .run();
     assert_eq!(result.intercept[0], Some(Blue));
 }
 
 fn ch13() {
     println!("ch13_duality_part2");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [0, INTERC0],
         instr: vec![
@@ -225,14 +237,14 @@ fn ch13() {
         ],
         mem: vec![I],
         ..default()
-    }
+    })
     .run();
     assert_eq!(result.intercept[0], Some(Red));
 }
 
 fn ch14() {
     println!("ch14_duality_part3");
-    let case0 = State {
+    let case0 = checked(State {
         balls: [8, 8],
         entry: [0, INTERC0],
         instr: vec![
@@ -240,7 +252,7 @@ fn ch14() {
         ],
         mem: vec![O],
         ..default()
-    };
+    });
 
     let case1 = case0.with_bit(0, I);
 
@@ -253,7 +265,7 @@ fn ch14() {
 
 fn ch15() {
     println!("ch15_inversion");
-    let case0 = State {
+    let case0 = checked(State {
         balls: [8, 8],
         entry: [0, INTERC0],
         instr: vec![
@@ -262,7 +274,7 @@ fn ch15() {
         ],
         mem: vec![I, O],
         ..default()
-    };
+    });
     let case1 = case0.with_bit(1, I);
 
     println!("case0");
@@ -274,7 +286,7 @@ fn ch15() {
 
 fn ch16() {
     println!("ch16_termination");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [0, FALL],
         instr: vec![
@@ -283,7 +295,7 @@ fn ch16() {
         ],
         mem: vec![O, O],
         ..default()
-    }
+    })
     .run();
     assert_eq!(&result.output_str(), "bbb");
     assert_eq!(result.intercept[0], Some(Blue));
@@ -292,7 +304,7 @@ fn ch16() {
 fn ch17() {
     // Note: test for unused inputs
     println!("ch17_fixed_ratio");
-    let result = State {
+    let result = checked(State {
         balls: [8, 8],
         entry: [0, 2],
         instr: vec![
@@ -305,7 +317,7 @@ fn ch17() {
         ],
         mem: vec![O, I, O, O],
         ..default()
-    }
+    })
     .run();
     assert_eq!(&result.output_str(), "bbbrrr");
 }
@@ -314,7 +326,7 @@ fn ch18() {
     // NAND gate.
     // Note: test for unused inputs
     println!("ch18_entanglement");
-    let p = State {
+    let p = checked(State {
         balls: [8, 8],
         entry: [0, FALL],
         instr: vec![
@@ -323,7 +335,7 @@ fn ch18() {
         ],
         mem: vec![O, O],
         ..default()
-    };
+    });
 
     println!("case 00 => F");
     assert_eq!(
@@ -350,7 +362,7 @@ fn ch18() {
 fn ch19() {
     // AND gate.
     println!("ch19_entanglement");
-    let p = State {
+    let p = checked(State {
         balls: [8, 8],
         entry: [0, INTERC0],
         instr: vec![
@@ -359,7 +371,7 @@ fn ch19() {
         ],
         mem: vec![O, O],
         ..default()
-    };
+    });
 
     println!("case 00 => r");
     assert_eq!(p.with_mem(vec![O, O]).run().intercept[0], Some(Red));
@@ -374,7 +386,7 @@ fn ch19() {
 fn ch20() {
     // OR gate.
     println!("ch20_symbiosis");
-    let p = State {
+    let p = checked(State {
         balls: [8, 8],
         entry: [0, INTERC0],
         instr: vec![
@@ -383,7 +395,7 @@ fn ch20() {
         ],
         mem: vec![O, O],
         ..default()
-    };
+    });
 
     println!("case 00 => r");
     assert_eq!(p.with_mem(vec![O, O]).run().intercept[0], Some(Red));
@@ -398,7 +410,7 @@ fn ch20() {
 fn ch21() {
     // 4-bit counter
     println!("ch21_quantum_number");
-    let p = State {
+    let p = checked(State {
         balls: [0, 0],
         entry: [0, FALL],
         instr: vec![
@@ -409,7 +421,7 @@ fn ch21() {
         ],
         mem: vec![O, O, O, O],
         ..default()
-    };
+    });
 
     for n in 0..16 {
         println!("case {}", n);
@@ -420,7 +432,7 @@ fn ch21() {
 fn ch22() {
     // 4-bit count down
     println!("ch22_depletion");
-    let p = State {
+    let p = checked(State {
         balls: [0, 0],
         entry: [0, FALL],
         instr: vec![
@@ -431,7 +443,7 @@ fn ch22() {
         ],
         mem: vec![I, I, I, I],
         ..default()
-    };
+    });
 
     for n in 0..16 {
         println!("case {}", n);
@@ -441,7 +453,7 @@ fn ch22() {
 
 fn ch23() {
     println!("ch23_tetrad");
-    let p = State {
+    let p = checked(State {
         balls: [8, 8],
         entry: [0, FALL],
         instr: vec![
@@ -451,14 +463,14 @@ fn ch23() {
         ],
         mem: vec![O, O, I],
         ..default()
-    };
+    });
 
     assert_eq!(p.run().output_str(), "bbbb");
 }
 
 fn ch24() {
     println!("ch24_ennead");
-    let p = State {
+    let p = checked(State {
         balls: [12, 12],
         entry: [0, FALL],
         instr: vec![
@@ -469,14 +481,14 @@ fn ch24() {
         ],
         mem: vec![I, O, O, I],
         ..default()
-    };
+    });
 
     assert_eq!(p.run().output_str(), "bbbbbbbbb");
 }
 
 fn ch25() {
     println!("ch25_regular_expression");
-    let p = State {
+    let p = checked(State {
         balls: [8, 8],
         entry: [0, 3],
         instr: vec![
@@ -490,14 +502,14 @@ fn ch25() {
         ],
         mem: vec![I, O, I, I, I],
         ..default()
-    };
+    });
 
     assert_eq!(p.run().output_str(), "bbbbbbrrr");
 }
 
 fn ch26() {
     println!("ch26_nucleus");
-    let p = State {
+    let p = checked(State {
         balls: [10, 10],
         entry: [0, 2],
         instr: vec![
@@ -509,7 +521,7 @@ fn ch26() {
         ],
         mem: vec![I, I, I],
         ..default()
-    };
+    });
 
     assert_eq!(p.run().output_str(), "bbbbrbbbb");
 }
@@ -518,7 +530,7 @@ fn ch27() {
     // Note: strong physical constraints
     // Note: high-cardinality specification (512 tests).
     println!("ch27_reflection");
-    let p = State {
+    let p = checked(State {
         balls: [8, 8],
         entry: [0, 9],
         instr: vec![
@@ -538,27 +550,36 @@ fn ch27() {
         ],
         mem: vec![I, O, O, O, O, O, O, O, O, O],
         ..default()
-    };
+    });
 
-    for n in 0..(1 << 9) {
-        let complement = (!n) & 0b111111111;
-        println!("case {}: {:09b} -> {:09b}", n, n, complement);
-        assert_eq!(p.with_register(1..10, n).run().register(1..10), complement);
+    // the 512 cases, run 64 at a time
+    for first in (0..(1 << 9)).step_by(LANES) {
+        let mut batch = Batch::new(&p, LANES);
+        for lane in 0..LANES {
+            batch.set_register(lane, 1..10, first + lane as u64);
+        }
+        let out = batch.run();
+        for lane in 0..LANES {
+            let n = first + lane as u64;
+            let complement = (!n) & 0b111111111;
+            println!("case {}: {:09b} -> {:09b}", n, n, complement);
+            assert_eq!(out.register(lane, 1..10), complement);
+        }
     }
 }
 
 fn ch28() {
     println!("ch28_latch");
-    let p = State {
+    let p = checked(State {
         balls: [8, 8],
-        entry: [0, FALL],
+        entry: [0, 0], // the latch never releases a red ball
         instr: vec![
             ijmp(0, 1, R),
             ijmp(0, FALL, B), // part of the latch @ mem[0]
         ],
         mem: vec![I],
         ..default()
-    };
+    });
     assert_eq!(p.run().output_str(), "bbbbbbbb");
 }
 
@@ -566,7 +587,7 @@ fn ch29() {
     // Note latch asymmetry. We could, in general, constrain that jmp0 <= jmp1 (except for special registers?),
     // to cull mirror symmetry.
     println!("ch29_one_shot_switch");
-    let p = State {
+    let p = checked(State {
         balls: [8, 8],
         entry: [0, B],
         instr: vec![
@@ -575,7 +596,7 @@ fn ch29() {
         ],
         mem: vec![O],
         ..default()
-    };
+    });
     assert_eq!(p.run().output_str(), "brbbbbbbb");
 }
 
@@ -584,7 +605,7 @@ fn ch30() {
     // to cull mirror symmetry.
 
     println!("ch30_overflow");
-    let p = State {
+    let p = checked(State {
         balls: [0, 0],
         entry: [0, B],
         instr: vec![
@@ -593,12 +614,12 @@ fn ch30() {
             ijmp(1, 2, B),
             ijmp(2, 3, B),
             // overflow latch
-            ijmp(3, 3, B),
+            ijmp(3, 4, B),
             ijmp(3, FALL, B),
         ],
         mem: vec![O, O, O, O],
         ..default()
-    };
+    });
 
     for n in 0..20 {
         //println!("case {}", n);
@@ -615,26 +636,26 @@ fn ch30() {
 
 fn ch31() {
     println!("ch31_supervised_learning");
-    let p = State {
+    let p = checked(State {
         balls: [0, 0],
-        entry: [6, FALL],
+        entry: [0, FALL],
         instr: vec![
+            // switch
+            ijmp(6, 1, 2),    //0
+            ijmp(6, FALL, 6), //1
+            ijmp(6, 3, FALL), //2
             // register A
-            ijmp(0, 1, B),
-            ijmp(1, 2, B),
+            ijmp(0, 4, B),
+            ijmp(1, 5, B),
             ijmp(2, B, B),
             // register B
-            ijmp(3, 4, B),
-            ijmp(4, 5, B),
+            ijmp(3, 7, B),
+            ijmp(4, 8, B),
             ijmp(5, B, B),
-            // switch
-            ijmp(6, 7, 8),    //6
-            ijmp(6, FALL, 3), //7
-            ijmp(6, 0, FALL), //8
         ],
         mem: vec![O; 7],
         ..default()
-    };
+    });
 
     let verbosity = 1;
     for n in 0..=7 {
@@ -652,13 +673,13 @@ fn ch31() {
 
 fn ch32() {
     println!("ch32_set_reset");
-    let p = State {
+    let p = checked(State {
         balls: [1, 1],
         entry: [0, 1],
         instr: vec![ijmp(0, B, 2), ijmp(0, 2, R), ijmp(0, B, R)],
         mem: vec![O],
         ..default()
-    };
+    });
 
     let b = p.with_start(Blue);
     let r = p.with_start(Red);
@@ -673,24 +694,24 @@ fn ch32() {
 
 fn ch33() {
     println!("ch33_teleportation");
-    let p = State {
+    let p = checked(State {
         balls: [8, 8],
-        entry: [6, 4],
+        entry: [0, 5],
         instr: vec![
+            // toggle @ mem[2]
+            ijmp(2, 1, 4), // 0
             // switch A @ mem[0]
-            ijmp(0, 1, 2),    // 0 (Input)
-            ijmp(0, FALL, R), // 1 (Output for I)
-            ijmp(0, B, FALL), // 2 (Output for O)
+            ijmp(0, 2, 3),    // 1 (Input)
+            ijmp(0, FALL, R), // 2 (Output for I)
+            ijmp(0, B, FALL), // 3 (Output for O)
             // set-reset B @ mem[1]
-            ijmp(1, INTERC0, 5),       // 3 (Reset)
-            ijmp(1, 5, INTERC0),       // 4 (Set)
-            ijmp(1, INTERC0, INTERC0), // 5 (Output)
-            // toggle @ mem[2]
-            ijmp(2, 0, 3), // 6
+            ijmp(1, INTERC0, 6),       // 4 (Reset)
+            ijmp(1, 6, INTERC0),       // 5 (Set)
+            ijmp(1, INTERC0, INTERC0), // 6 (Output)
         ],
         mem: vec![O, O, I],
         ..default()
-    };
+    });
 
     for init_a in [O, I] {
         for init_b in [O, I] {
@@ -0,0 +1,229 @@
+use magnus::*;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process::exit;
+
+// Interactive debugger for programs in the `State` text format, or for
+// boards in the `Board` text format (files ending in `.board`).
+// Usage:
+//     tumble PROGRAM_FILE | BOARD_FILE
+// Commands:
+//     step [N]              execute N instructions (default 1)
+//     ball                  run until the next ball is released
+//     run                   run until the machine halts or hits a breakpoint
+//     break ADDR            stop before instruction ADDR
+//     break mem I           stop after mem[I] changed
+//     delete ADDR | mem I   remove a breakpoint
+//     set mem I 0|1         set a memory bit
+//     balls B R             set the number of blue, red balls
+//     start b|r             set the start button, and reset
+//     print                 print the complete state
+//     board                 print a layout of the program on the board
+//     svg graph|board FILE  write the program graph, or its layout, as SVG
+//     dot FILE              write the program graph in Graphviz DOT format
+//     reset                 start over, keeping the settings above
+//     help, quit
+// Settings apply to the current run, and are kept by `reset`.
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: tumble PROGRAM_FILE | BOARD_FILE");
+            exit(1)
+        }
+    };
+    let src = fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        exit(1)
+    });
+    let program = if path.ends_with(".board") {
+        let board = src.parse::<Board>().map_err(ProgramError::Parse);
+        board.map(|board| board.to_state().unwrap())
+    } else {
+        State::parse_checked(&src)
+    };
+    let program = program.unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        exit(1)
+    });
+    for diag in program.validate() {
+        println!("{}", diag);
+    }
+
+    let mut session = Session::new(program.with_balls([8, 8]));
+    session.print_status();
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            return; // end of input
+        }
+        let args: Vec<&str> = line.split_whitespace().collect();
+        match args.first() {
+            None => (),
+            Some(&"quit") | Some(&"q") => return,
+            Some(_) => {
+                if let Err(msg) = session.command(&args) {
+                    println!("{}", msg);
+                }
+            }
+        }
+    }
+}
+
+struct Session {
+    /// The program and settings to (re)start from.
+    init: State,
+    machine: Machine,
+}
+
+impl Session {
+    fn new(init: State) -> Self {
+        let machine = Machine::new(init.clone());
+        Self { init, machine }
+    }
+
+    fn command(&mut self, args: &[&str]) -> Result<(), String> {
+        let m = &mut self.machine;
+        match args {
+            ["step"] => self.step(1)?,
+            ["step", n] => self.step(number(n)?)?,
+            ["ball"] => {
+                let stop = m.step_ball().map_err(|err| err.to_string())?;
+                self.print_stop(stop);
+            }
+            ["run"] => {
+                let stop = m.run().map_err(|err| err.to_string())?;
+                self.print_stop(stop);
+            }
+            ["break", "mem", i] => m.add_breakpoint(Breakpoint::Mem(number(i)?)),
+            ["break", addr] => m.add_breakpoint(Breakpoint::Instr(addr_arg(addr)?)),
+            ["delete", "mem", i] => m.remove_breakpoint(Breakpoint::Mem(number(i)?)),
+            ["delete", addr] => m.remove_breakpoint(Breakpoint::Instr(addr_arg(addr)?)),
+            ["set", "mem", i, bit] => {
+                let i: usize = number(i)?;
+                let bit = match *bit {
+                    "0" => false,
+                    "1" => true,
+                    _ => return Err(format!("expected 0 or 1, found `{}`", bit)),
+                };
+                if i >= self.init.mem.len() {
+                    return Err(format!("no such memory address: {}", i));
+                }
+                self.init.mem[i] = bit;
+                m.state_mut().mem[i] = bit;
+                self.print_status();
+            }
+            ["balls", b, r] => {
+                let balls = [number(b)?, number(r)?];
+                self.init.balls = balls;
+                m.state_mut().balls = balls;
+                self.print_status();
+            }
+            ["start", color] => {
+                let mut chars = color.chars();
+                self.init.start_button =
+                    match (chars.next().and_then(Color::from_char), chars.next()) {
+                        (Some(color), None) => color,
+                        _ => return Err(format!("expected b or r, found `{}`", color)),
+                    };
+                self.reset();
+            }
+            ["print"] => {
+                print!("{}", m.state());
+                self.print_status();
+            }
+            ["board"] => print!("{}", self.init.to_board().map_err(|err| err.to_string())?),
+            ["svg", what, file] => {
+                let svg = match *what {
+                    "graph" => self.init.to_svg(),
+                    "board" => self.init.to_board().map_err(|err| err.to_string())?.to_svg(),
+                    _ => return Err(format!("expected graph or board, found `{}`", what)),
+                };
+                fs::write(file, svg).map_err(|err| format!("{}: {}", file, err))?;
+            }
+            ["dot", file] => {
+                fs::write(file, self.init.to_dot()).map_err(|err| format!("{}: {}", file, err))?
+            }
+            ["reset"] => self.reset(),
+            ["help"] => println!(
+                "commands: step [N], ball, run, break ADDR | mem I, delete ADDR | mem I, \
+                 set mem I 0|1, balls B R, start b|r, print, board, svg graph|board FILE, dot FILE, reset, quit"
+            ),
+            _ => {
+                return Err(format!(
+                    "unknown command: `{}` (try `help`)",
+                    args.join(" ")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, n: usize) -> Result<(), String> {
+        for _ in 0..n {
+            self.machine.step_instr().map_err(|err| err.to_string())?;
+        }
+        self.print_status();
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        let breakpoints = self.machine.breakpoints().to_vec();
+        self.machine = Machine::new(self.init.clone());
+        for b in breakpoints {
+            self.machine.add_breakpoint(b);
+        }
+        self.print_status();
+    }
+
+    fn print_stop(&self, stop: Stop) {
+        match stop {
+            Stop::Breakpoint(Breakpoint::Instr(addr)) => {
+                println!("breakpoint: instruction {}", addr)
+            }
+            Stop::Breakpoint(Breakpoint::Mem(addr)) => {
+                println!("breakpoint: mem[{}] changed", addr)
+            }
+            Stop::Halted | Stop::Condition => (),
+        }
+        self.print_status();
+    }
+
+    /// E.g. `PC 3, ball 2 (b) | balls 5 8 | mem 0110 | out bbr`
+    fn print_status(&self) {
+        let m = &self.machine;
+        let s = m.state();
+        match (m.pc(), m.cbr()) {
+            (Some(pc), Some(cbr)) => {
+                print!("PC {}, ball {} ({})", DisplayAddr(pc), m.ball(), cbr)
+            }
+            _ => print!("halted"),
+        }
+        print!(
+            " | balls {} {} | mem {} | out {}",
+            s.balls[0],
+            s.balls[1],
+            s.mem_str(),
+            s.output_str()
+        );
+        for (i, ball) in s.intercept.iter().enumerate() {
+            if let Some(ball) = ball {
+                print!(" | INTERC{}: {}", i, ball);
+            }
+        }
+        println!();
+    }
+}
+
+fn number<T: std::str::FromStr>(arg: &str) -> Result<T, String> {
+    arg.parse()
+        .map_err(|_| format!("invalid number: `{}`", arg))
+}
+
+fn addr_arg(arg: &str) -> Result<Addr, String> {
+    parse_addr(arg).ok_or_else(|| format!("invalid address: `{}`", arg))
+}
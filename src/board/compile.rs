@@ -0,0 +1,116 @@
+use super::*;
+use std::collections::HashMap;
+
+impl Board {
+    /// Compile the board into a program with the same behaviour.
+    ///
+    /// Bits and gear bits become instructions, numbered in reading order
+    /// (which is topological, since balls only move down). A bit's memory
+    /// address is its own instruction address, and gear bits connected by
+    /// gears share the memory address of the first of them. The initial
+    /// memory is the orientation of the bits, `jmp0` follows the left output
+    /// and `jmp1` the right output through ramps and crossovers. Paths that
+    /// end in an empty cell, a gear, or outside the board go to `FALL`.
+    ///
+    /// Ball counts and the start button are left at their default values.
+    pub fn to_state(&self) -> Result<State, BoardError> {
+        let bits: Vec<(usize, usize, bool)> = self
+            .parts()
+            .filter_map(|(r, c, part)| match part {
+                Part::Bit(value) | Part::GearBit(value) => Some((r, c, value)),
+                _ => None,
+            })
+            .collect();
+        if bits.len() > BLUE_LEVER as usize {
+            return Err(BoardError::TooManyBits(bits.len()));
+        }
+        let n_interceptors = self
+            .parts()
+            .filter(|&(_, _, p)| p == Part::Interceptor)
+            .count();
+        if n_interceptors > 3 {
+            return Err(BoardError::TooManyInterceptors(n_interceptors));
+        }
+        let addr: HashMap<(usize, usize), Addr> = bits
+            .iter()
+            .enumerate()
+            .map(|(i, &(r, c, _))| ((r, c), i as Addr))
+            .collect();
+
+        let target = |landing: Landing| match landing {
+            Landing::Lever(lever) => lever,
+            Landing::FellOff(..) => FALL,
+            Landing::Part(r, c, Part::Interceptor) => {
+                INTERC0 + self.interceptor_index(r, c) as Addr
+            }
+            Landing::Part(r, c, _) => addr[&(r, c)],
+        };
+
+        let mut p = State {
+            mem: bits.iter().map(|&(_, _, value)| value).collect(),
+            ..State::default()
+        };
+        for (i, &(r, c, value)) in bits.iter().enumerate() {
+            let mut mem = i as Addr;
+            if let Some(Part::GearBit(_)) = self.get(r, c) {
+                for (r, c) in self.gear_train(r, c) {
+                    if let Some(Part::GearBit(v)) = self.get(r, c) {
+                        if v != value {
+                            return Err(BoardError::GearsDisagree { row: r, col: c });
+                        }
+                        mem = mem.min(addr[&(r, c)]);
+                    }
+                }
+            }
+            p.instr.push(Instr {
+                mem,
+                jmp0: target(self.slide(r + 1, c as isize - 1, Dir::Left)),
+                jmp1: target(self.slide(r + 1, c as isize + 1, Dir::Right)),
+            });
+        }
+        for color in [Color::Blue, Color::Red] {
+            let (row, col, dir) = self.entry(color);
+            p.entry[usize::from(color)] = target(self.slide(row, col as isize, dir));
+        }
+        Ok(p)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const B: Addr = BLUE_LEVER;
+    const R: Addr = RED_LEVER;
+
+    #[test]
+    fn compile() {
+        let mut board = Board::new(3, 7);
+        // blue enters at (0, 1), red at (0, 5)
+        board.set(0, 1, Some(Part::Bit(true)));
+        board.set(1, 0, Some(Part::Ramp(Dir::Right)));
+        board.set(1, 2, Some(Part::GearBit(false)));
+        board.set(1, 3, Some(Part::Gear));
+        board.set(1, 4, Some(Part::GearBit(false)));
+        board.set(0, 5, Some(Part::Ramp(Dir::Left)));
+        board.set(2, 1, Some(Part::Crossover));
+        board.set(2, 3, Some(Part::Interceptor));
+        board.set(2, 5, Some(Part::Ramp(Dir::Right)));
+        let p = board.to_state().unwrap();
+        assert_eq!(p.entry, [0, 2]);
+        assert_eq!(p.mem, vec![true, false, false]);
+        assert_eq!(
+            p.instr.iter().map(Instr::to_string).collect::<Vec<_>>(),
+            vec!["ijmp 0 B 1", "ijmp 1 B INTERC0", "ijmp 1 INTERC0 R"]
+        );
+        assert_eq!(p.instr[0].jmp0, B);
+        assert_eq!(p.instr[2].jmp1, R);
+
+        board.set(1, 4, Some(Part::GearBit(true)));
+        assert_eq!(
+            board.to_state().unwrap_err(),
+            BoardError::GearsDisagree { row: 1, col: 4 }
+        );
+    }
+}
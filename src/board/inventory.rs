@@ -0,0 +1,89 @@
+use super::*;
+
+/// Number of parts of each kind: available for a layout (see
+/// `LayoutOptions::with_inventory`), or used by a board (`Inventory::of`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Inventory {
+    pub ramps: usize,
+    pub crossovers: usize,
+    pub bits: usize,
+    pub gear_bits: usize,
+    pub gears: usize,
+    pub interceptors: usize,
+}
+
+impl Inventory {
+    /// No limits.
+    pub fn unlimited() -> Self {
+        Self {
+            ramps: usize::MAX,
+            crossovers: usize::MAX,
+            bits: usize::MAX,
+            gear_bits: usize::MAX,
+            gears: usize::MAX,
+            interceptors: usize::MAX,
+        }
+    }
+
+    /// The parts of a Turing Tumble game box.
+    pub fn turing_tumble() -> Self {
+        Self {
+            ramps: 30,
+            crossovers: 6,
+            bits: 8,
+            gear_bits: 6,
+            gears: 4,
+            interceptors: 3,
+        }
+    }
+
+    /// The parts used by a board.
+    pub fn of(board: &Board) -> Self {
+        let mut used = Self {
+            ramps: 0,
+            crossovers: 0,
+            bits: 0,
+            gear_bits: 0,
+            gears: 0,
+            interceptors: 0,
+        };
+        for (_, _, part) in board.parts() {
+            *match part {
+                Part::Ramp(_) => &mut used.ramps,
+                Part::Crossover => &mut used.crossovers,
+                Part::Bit(_) => &mut used.bits,
+                Part::GearBit(_) => &mut used.gear_bits,
+                Part::Gear => &mut used.gears,
+                Part::Interceptor => &mut used.interceptors,
+            } += 1;
+        }
+        used
+    }
+
+    /// Are there enough parts of each kind for `used`?
+    pub fn contains(&self, used: &Inventory) -> bool {
+        used.ramps <= self.ramps
+            && used.crossovers <= self.crossovers
+            && used.bits <= self.bits
+            && used.gear_bits <= self.gear_bits
+            && used.gears <= self.gears
+            && used.interceptors <= self.interceptors
+    }
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+/// E.g. `12 ramps, 1 crossovers, 3 bits, 0 gear bits, 0 gears, 1 interceptors`
+impl fmt::Display for Inventory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ramps, {} crossovers, {} bits, {} gear bits, {} gears, {} interceptors",
+            self.ramps, self.crossovers, self.bits, self.gear_bits, self.gears, self.interceptors
+        )
+    }
+}
@@ -0,0 +1,999 @@
+use super::*;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Why `layout` failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LayoutError {
+    /// The program has errors, see `State::validate`.
+    Invalid(Diagnostic),
+    /// The program needs more parts of a kind than the inventory has,
+    /// e.g. `part: "bits"`.
+    NotEnoughParts {
+        part: &'static str,
+        needed: usize,
+        available: usize,
+    },
+    /// No layout was found on a board of this size, with these parts.
+    NoFit { rows: usize, cols: usize },
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutError::Invalid(diag) => write!(f, "invalid program: {}", diag),
+            LayoutError::NotEnoughParts {
+                part,
+                needed,
+                available,
+            } => write!(
+                f,
+                "not enough {}: {} needed, {} available",
+                part, needed, available
+            ),
+            LayoutError::NoFit { rows, cols } => {
+                write!(f, "no layout found on a {}x{} board", cols, rows)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+/// Options for `layout_with`.
+#[derive(Clone, Debug)]
+pub struct LayoutOptions {
+    pub rows: usize,
+    pub cols: usize,
+    /// Parts available.
+    pub inventory: Inventory,
+    /// If the instructions cannot be placed in program order, try other
+    /// orders (in which jumps still only go down the board).
+    pub reorder: bool,
+    /// Give up after trying this many placements, per order.
+    pub max_attempts: usize,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            rows: 11,
+            cols: 11,
+            inventory: Inventory::unlimited(),
+            reorder: true,
+            max_attempts: 20_000,
+        }
+    }
+}
+
+impl LayoutOptions {
+    pub fn with_size(&self, rows: usize, cols: usize) -> Self {
+        Self {
+            rows,
+            cols,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_inventory(&self, inventory: Inventory) -> Self {
+        Self {
+            inventory,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_reorder(&self, reorder: bool) -> Self {
+        Self {
+            reorder,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_max_attempts(&self, max_attempts: usize) -> Self {
+        Self {
+            max_attempts,
+            ..self.clone()
+        }
+    }
+}
+
+/// Try at most this many instruction orders.
+const MAX_ORDERS: usize = 32;
+
+impl State {
+    /// Lay out the program on the standard board, see `layout_with`.
+    pub fn to_board(&self) -> Result<Board, LayoutError> {
+        layout_with(self, &LayoutOptions::default())
+    }
+}
+
+/// Lay out the program on a board of the given size, see `layout_with`.
+pub fn layout(p: &State, rows: usize, cols: usize) -> Result<Board, LayoutError> {
+    layout_with(p, &LayoutOptions::default().with_size(rows, cols))
+}
+
+/// Find a physical realisation of a program: place and route it on a board.
+///
+/// Every reachable instruction becomes a bit, or a gear bit if it shares its
+/// memory address with other instructions, and its jumps become paths of
+/// ramps down to the target, merging with paths to the same target, and
+/// crossing other paths with crossovers. Interceptors are placed in reading
+/// order (`INTERC0` first). Gear bits that share a memory address are
+/// connected by a chain of gears. A memory address past the last instruction
+/// (a memory-only bit, see `search_gear`) that reachable instructions use
+/// also gets a gear bit of its own on that chain, placed where no ball can
+/// reach it.
+///
+/// A bit's outputs can be swapped by flipping its initial value (mirroring
+/// the part), which is tried whenever that helps routing: the initial
+/// orientation of a part is `mem ^ mirrored`, so a bit that reads 1 may be
+/// built pointing left. Memory only read by unreachable instructions is not
+/// built at all.
+///
+/// The layout uses no more parts than `opts.inventory` (a straight piece
+/// of path takes a crossover when the ramps run out), and fails with
+/// `NotEnoughParts` if the program needs too many bits, gear bits or
+/// interceptors, or `NoFit` if no layout was found.
+pub fn layout_with(p: &State, opts: &LayoutOptions) -> Result<Board, LayoutError> {
+    if let Some(diag) = p.validate().into_iter().find(Diagnostic::is_error) {
+        return Err(LayoutError::Invalid(diag));
+    }
+    let (released, reachable) = p.reachable();
+    let colors = [Color::Blue, Color::Red]
+        .iter()
+        .copied()
+        .filter(|&c| released[usize::from(c)])
+        .collect::<Vec<_>>();
+
+    let instrs: Vec<Addr> = (0..p.instr.len())
+        .filter(|&i| reachable[i])
+        .map(|i| i as Addr)
+        .collect();
+    let mut targets: Vec<Addr> = colors.iter().map(|&c| p.entry[usize::from(c)]).collect();
+    for &i in &instrs {
+        targets.extend([p.instr[i as usize].jmp0, p.instr[i as usize].jmp1]);
+    }
+    // also unused interceptors before the last used one, to keep their numbering
+    let interceptors: Vec<Addr> = match targets
+        .iter()
+        .filter(|&&t| (INTERC0..=INTERC2).contains(&t))
+        .max()
+    {
+        Some(&last) => (INTERC0..=last).collect(),
+        None => vec![],
+    };
+
+    let mut n_shared = vec![0; p.mem.len()];
+    for &i in &instrs {
+        n_shared[p.instr[i as usize].mem as usize] += 1;
+    }
+    let memory_only = |mem: usize| mem >= p.instr.len() && n_shared[mem] > 0;
+    let geared: Vec<bool> = (0..p.mem.len())
+        .map(|mem| n_shared[mem] > 1 || memory_only(mem))
+        .collect();
+    let n_gear_bits = instrs
+        .iter()
+        .filter(|&&i| geared[p.instr[i as usize].mem as usize])
+        .count()
+        + (0..p.mem.len()).filter(|&mem| memory_only(mem)).count();
+    let inventory = &opts.inventory;
+    for (part, needed, available) in [
+        ("bits", instrs.len() - n_gear_bits, inventory.bits),
+        ("gear bits", n_gear_bits, inventory.gear_bits),
+        ("interceptors", interceptors.len(), inventory.interceptors),
+    ] {
+        if needed > available {
+            return Err(LayoutError::NotEnoughParts {
+                part,
+                needed,
+                available,
+            });
+        }
+    }
+
+    let mut search = Search {
+        p,
+        board: Board::new(opts.rows, opts.cols),
+        inventory: opts.inventory,
+        max_attempts: opts.max_attempts,
+        colors,
+        nodes: vec![],
+        geared,
+        attempts: 0,
+    };
+    let mut grid = Grid {
+        cols: opts.cols,
+        cells: vec![Cell::Empty; opts.rows * opts.cols],
+        pos: vec![None; INTERC2 as usize + 1],
+        mirror: vec![None; p.mem.len()],
+    };
+    let no_fit = LayoutError::NoFit {
+        rows: opts.rows,
+        cols: opts.cols,
+    };
+    for &color in &search.colors {
+        let entry = p.entry[usize::from(color)];
+        if (entry == BLUE_LEVER || entry == RED_LEVER)
+            && !search.route(&mut grid, search.entry_port(color), entry)
+        {
+            return Err(no_fit);
+        }
+    }
+
+    let orders = if opts.reorder {
+        topological_orders(p, &instrs, MAX_ORDERS)
+    } else {
+        vec![instrs]
+    };
+    for order in orders {
+        search.nodes = order;
+        search.nodes.extend(&interceptors);
+        search.attempts = 0;
+        if let Some(grid) = search.place(0, &grid) {
+            return Ok(search.board(&grid));
+        }
+    }
+    Err(no_fit)
+}
+
+/// Up to `max` orders of `instrs` in which all jumps go forward,
+/// program order first.
+fn topological_orders(p: &State, instrs: &[Addr], max: usize) -> Vec<Vec<Addr>> {
+    fn extend(
+        p: &State,
+        instrs: &[Addr],
+        order: &mut Vec<Addr>,
+        orders: &mut Vec<Vec<Addr>>,
+        max: usize,
+    ) {
+        if orders.len() >= max {
+            return;
+        }
+        if order.len() == instrs.len() {
+            orders.push(order.clone());
+            return;
+        }
+        for &i in instrs {
+            // all jumps into i come from instructions already in the order
+            let ready = !order.contains(&i)
+                && instrs.iter().all(|&j| {
+                    order.contains(&j)
+                        || j == i
+                        || (p.instr[j as usize].jmp0 != i && p.instr[j as usize].jmp1 != i)
+                });
+            if ready {
+                order.push(i);
+                extend(p, instrs, order, orders, max);
+                order.pop();
+            }
+        }
+    }
+    let mut orders = vec![];
+    extend(p, instrs, &mut vec![], &mut orders, max);
+    orders
+}
+
+/// The contents of a cell during the search.
+#[derive(Clone, Debug)]
+enum Cell {
+    Empty,
+    /// An instruction or an interceptor.
+    Node(Addr),
+    /// Ball paths through this cell: the direction they come from, the
+    /// direction they leave in, and where they go to.
+    Track(Vec<(Dir, Dir, Addr)>),
+    /// A gear connecting the gear bits of this memory address.
+    Gear(Addr),
+    /// The gear bit of a memory-only address, which no ball reaches.
+    MemBit(Addr),
+}
+
+#[derive(Clone, Debug)]
+struct Grid {
+    cols: usize,
+    cells: Vec<Cell>,
+    /// Where each node is, indexed by address.
+    pos: Vec<Option<(usize, usize)>>,
+    /// Whether the bits of each memory address are mirrored, if decided.
+    mirror: Vec<Option<bool>>,
+}
+
+impl Grid {
+    fn cell(&self, (row, col): (usize, usize)) -> &Cell {
+        &self.cells[row * self.cols + col]
+    }
+
+    fn cell_mut(&mut self, (row, col): (usize, usize)) -> &mut Cell {
+        &mut self.cells[row * self.cols + col]
+    }
+}
+
+/// A ball about to enter cell `(row, col)` moving in direction `Dir`.
+/// May be outside the board.
+type Port = (isize, isize, Dir);
+
+/// What happens to a ball entering a cell during routing.
+enum Enter {
+    /// Reached the target, or a path to the target that it can follow,
+    /// leaving in this direction.
+    Done(Option<Dir>),
+    /// Empty: a ramp can be put here.
+    Free,
+    /// A path going straight in the other direction: turn its ramp into a
+    /// crossover, and go straight.
+    Cross,
+    Blocked,
+}
+
+/// A cell on a route, see `Search::route`.
+struct Visit {
+    row: usize,
+    col: usize,
+    din: Dir,
+    dout: Dir,
+    how: Option<Enter>,
+    prev: Option<usize>,
+}
+
+struct Search<'a> {
+    p: &'a State,
+    /// Empty, for the geometry.
+    board: Board,
+    /// Colors of the balls that may be released.
+    colors: Vec<Color>,
+    /// Instructions and interceptors to place, in order.
+    nodes: Vec<Addr>,
+    /// Which memory addresses are shared by several instructions, or are
+    /// memory-only.
+    geared: Vec<bool>,
+    inventory: Inventory,
+    max_attempts: usize,
+    attempts: usize,
+}
+
+impl Search<'_> {
+    /// Place `nodes[k..]` and route the jumps into them, backtracking over
+    /// the position of each node, and the mirroring of each memory address.
+    fn place(&mut self, k: usize, grid: &Grid) -> Option<Grid> {
+        if k == self.nodes.len() {
+            let mut grid = grid.clone();
+            let fits = self.connect_gears(&mut grid)
+                && grid
+                    .cells
+                    .iter()
+                    .filter(|c| matches!(c, Cell::Gear(_)))
+                    .count()
+                    <= self.inventory.gears;
+            return fits.then_some(grid);
+        }
+        let node = self.nodes[k];
+        let mirrors = match node {
+            i if i < BLUE_LEVER => match grid.mirror[self.p.instr[i as usize].mem as usize] {
+                Some(m) => vec![m],
+                None => vec![false, true],
+            },
+            _ => vec![false],
+        };
+        for cell in self.candidates(node, grid) {
+            for &mirror in &mirrors {
+                self.attempts += 1;
+                if self.attempts > self.max_attempts {
+                    return None;
+                }
+                let mut g = grid.clone();
+                if self.put(&mut g, node, cell, mirror) && self.enough_track(&g) {
+                    if let Some(done) = self.place(k + 1, &g) {
+                        return Some(done);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Put `node` at `cell`, and route all jumps into it from the nodes
+    /// placed so far, and if it is an instruction, its jumps to levers.
+    fn put(&self, g: &mut Grid, node: Addr, cell: (usize, usize), mirror: bool) -> bool {
+        *g.cell_mut(cell) = Cell::Node(node);
+        g.pos[node as usize] = Some(cell);
+        if node < BLUE_LEVER {
+            g.mirror[self.p.instr[node as usize].mem as usize] = Some(mirror);
+        }
+        for port in self.incoming(g, node) {
+            if !self.route(g, port, node) {
+                return false;
+            }
+        }
+        if node < BLUE_LEVER {
+            for (port, target) in self.outputs(g, node) {
+                if (target == BLUE_LEVER || target == RED_LEVER) && !self.route(g, port, target) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn entry_port(&self, color: Color) -> Port {
+        let (row, col, dir) = self.board.entry(color);
+        (row as isize, col as isize, dir)
+    }
+
+    /// The left and right outputs of a placed instruction, and their targets.
+    fn outputs(&self, g: &Grid, i: Addr) -> [(Port, Addr); 2] {
+        let instr = self.p.instr[i as usize];
+        let (row, col) = g.pos[i as usize].unwrap();
+        let (row, col) = (row as isize, col as isize);
+        let (left, right) = match g.mirror[instr.mem as usize] {
+            Some(true) => (instr.jmp1, instr.jmp0),
+            _ => (instr.jmp0, instr.jmp1),
+        };
+        [
+            ((row + 1, col - 1, Dir::Left), left),
+            ((row + 1, col + 1, Dir::Right), right),
+        ]
+    }
+
+    /// Where balls for `node` come from: entrypoints, and outputs of placed
+    /// instructions.
+    fn incoming(&self, g: &Grid, node: Addr) -> Vec<Port> {
+        let mut ports = vec![];
+        for &color in &self.colors {
+            if self.p.entry[usize::from(color)] == node {
+                ports.push(self.entry_port(color));
+            }
+        }
+        for &i in &self.nodes {
+            if i < BLUE_LEVER && g.pos[i as usize].is_some() {
+                for (port, target) in self.outputs(g, i) {
+                    if target == node {
+                        ports.push(port)
+                    }
+                }
+            }
+        }
+        ports
+    }
+
+    /// Empty cells that all incoming balls can reach, best first: high up on
+    /// the board, and close to where the balls come from.
+    fn candidates(&self, node: Addr, g: &Grid) -> Vec<(usize, usize)> {
+        let ports = self.incoming(g, node);
+        // interceptors are numbered in reading order
+        let after = match node {
+            INTERC0 => None,
+            i if i > INTERC0 => g.pos[i as usize - 1],
+            _ => None,
+        };
+        let mut cells = vec![];
+        for row in 0..self.board.rows() {
+            for col in 0..self.board.cols() {
+                let (r, c) = (row as isize, col as isize);
+                let reachable = ports
+                    .iter()
+                    .all(|&(pr, pc, _)| r >= pr && (c - pc).abs() <= r - pr);
+                if matches!(g.cell((row, col)), Cell::Empty)
+                    && Board::on_lattice(row, col)
+                    && reachable
+                    && after.is_none_or(|a| (row, col) > a)
+                {
+                    let dist: isize = ports.iter().map(|&(_, pc, _)| (c - pc).abs()).sum();
+                    cells.push(((row, dist, col), (row, col)));
+                }
+            }
+        }
+        if ports.is_empty() {
+            // an unused interceptor: out of the way
+            cells.reverse();
+        } else {
+            cells.sort();
+        }
+        cells.into_iter().map(|(_, cell)| cell).collect()
+    }
+
+    fn enter(&self, g: &Grid, cell: (usize, usize), din: Dir, target: Addr) -> Enter {
+        match g.cell(cell) {
+            Cell::Node(node) if *node == target => Enter::Done(None),
+            Cell::Node(_) | Cell::Gear(_) | Cell::MemBit(_) => Enter::Blocked,
+            Cell::Empty => Enter::Free,
+            Cell::Track(paths) => {
+                let ramp = paths.iter().all(|p| p.1 == paths[0].1);
+                let (exit, net) = if ramp {
+                    (paths[0].1, paths[0].2)
+                } else {
+                    match paths.iter().find(|p| p.0 == din) {
+                        Some(p) => (din, p.2),
+                        None => return Enter::Blocked,
+                    }
+                };
+                if net == target {
+                    Enter::Done(Some(exit))
+                } else if ramp && exit != din && paths.iter().all(|p| p.0 == exit) {
+                    Enter::Cross
+                } else {
+                    Enter::Blocked
+                }
+            }
+        }
+    }
+
+    /// Route a ball from `from` down to `target` (a placed node, or a lever),
+    /// and add the path to the grid. Prefers paths that use few new parts,
+    /// merging early with paths to the same target, and few turns.
+    /// Since balls move down one row per cell, this is a shortest path search
+    /// over the rows, one at a time.
+    fn route(&self, g: &mut Grid, from: Port, target: Addr) -> bool {
+        const NEW: u32 = 10;
+        const TURN: u32 = 1;
+        const CROSS: u32 = 6;
+
+        let (rows, cols) = (self.board.rows() as isize, self.board.cols() as isize);
+        let mut visits: Vec<Visit> = vec![];
+        // best route found: cost, last visit
+        let mut best: Option<(u32, Option<usize>)> = None;
+        // balls about to enter the current row: col, direction, cost, last visit
+        let mut frontier = vec![(from.1, from.2, 0, None)];
+        let mut row = from.0;
+        while !frontier.is_empty() && best.is_none_or(|(b, _)| frontier.iter().any(|f| f.2 < b)) {
+            let mut next: BTreeMap<(isize, Dir), (u32, usize)> = BTreeMap::new();
+            for (col, din, cost, prev) in frontier {
+                if best.is_some_and(|(b, _)| cost >= b) || col < 0 || col >= cols {
+                    continue;
+                }
+                if row == rows {
+                    if self.board.lever(col as usize) == target {
+                        best = Some((cost, prev));
+                    }
+                    continue;
+                }
+                let cell = (row as usize, col as usize);
+                let mut visit =
+                    |dout, how: Enter, cost, best: &mut Option<(u32, Option<usize>)>| {
+                        visits.push(Visit {
+                            row: cell.0,
+                            col: cell.1,
+                            din,
+                            dout,
+                            how: Some(how),
+                            prev,
+                        });
+                        let v = visits.len() - 1;
+                        match visits[v].how {
+                            Some(Enter::Done(_)) => {
+                                if best.is_none_or(|(b, _)| cost < b) {
+                                    *best = Some((cost, Some(v)))
+                                }
+                            }
+                            _ => {
+                                let step = if dout == Dir::Left { -1 } else { 1 };
+                                let key = (col + step, dout);
+                                if next.get(&key).is_none_or(|&(c, _)| cost < c) {
+                                    next.insert(key, (cost, v));
+                                }
+                            }
+                        }
+                    };
+                match self.enter(g, cell, din, target) {
+                    Enter::Done(None) => {
+                        // the target itself: nothing to build here
+                        if best.is_none_or(|(b, _)| cost < b) {
+                            best = Some((cost, prev))
+                        }
+                    }
+                    Enter::Done(Some(exit)) => {
+                        visit(exit, Enter::Done(Some(exit)), cost, &mut best)
+                    }
+                    Enter::Free => {
+                        for dout in [Dir::Left, Dir::Right] {
+                            let turn = if dout == din { 0 } else { TURN };
+                            visit(dout, Enter::Free, cost + NEW + turn, &mut best)
+                        }
+                    }
+                    Enter::Cross => visit(din, Enter::Cross, cost + CROSS, &mut best),
+                    Enter::Blocked => (),
+                }
+            }
+            frontier = next
+                .into_iter()
+                .map(|((col, dir), (cost, v))| (col, dir, cost, Some(v)))
+                .collect();
+            row += 1;
+        }
+
+        let mut v = match best {
+            None => return false,
+            Some((_, v)) => v,
+        };
+        while let Some(i) = v {
+            let Visit {
+                row,
+                col,
+                din,
+                dout,
+                ref how,
+                prev,
+            } = visits[i];
+            let cell = g.cell_mut((row, col));
+            match (how, &mut *cell) {
+                (Some(Enter::Free), _) => *cell = Cell::Track(vec![(din, dout, target)]),
+                (_, Cell::Track(paths)) => {
+                    if !paths.contains(&(din, dout, target)) {
+                        paths.push((din, dout, target))
+                    }
+                }
+                _ => unreachable!(),
+            }
+            v = prev;
+        }
+        true
+    }
+
+    /// Connect the gear bits of each shared memory address with gears, and
+    /// add the gear bits of memory-only addresses.
+    fn connect_gears(&self, g: &mut Grid) -> bool {
+        for mem in 0..self.geared.len() {
+            if !self.geared[mem] {
+                continue;
+            }
+            let members: Vec<(usize, usize)> = self
+                .nodes
+                .iter()
+                .filter(|&&i| i < BLUE_LEVER && self.p.instr[i as usize].mem as usize == mem)
+                .map(|&i| g.pos[i as usize].unwrap())
+                .collect();
+            let mut connected = vec![members[0]];
+            for &member in &members[1..] {
+                let next_to_member = |cell| self.neighbours(cell).contains(&member);
+                if !connected.iter().any(|&c| next_to_member(c)) {
+                    match self.gear_path(g, &connected, mem as Addr, next_to_member) {
+                        None => return false,
+                        Some(path) => {
+                            for &cell in &path {
+                                *g.cell_mut(cell) = Cell::Gear(mem as Addr);
+                            }
+                            connected.extend(path);
+                        }
+                    }
+                }
+                connected.push(member);
+            }
+            if mem >= self.p.instr.len() {
+                match self.gear_path(g, &connected, mem as Addr, |cell| {
+                    self.out_of_reach(g, cell)
+                }) {
+                    None => return false,
+                    Some(path) => {
+                        *g.cell_mut(path[0]) = Cell::MemBit(mem as Addr);
+                        for &cell in &path[1..] {
+                            *g.cell_mut(cell) = Cell::Gear(mem as Addr);
+                        }
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Shortest chain of gears from a cell next to `connected` to a cell
+    /// where `done`, through empty cells that do not mesh with the gears of
+    /// other memory addresses. The last cell of the chain comes first.
+    fn gear_path(
+        &self,
+        g: &Grid,
+        connected: &[(usize, usize)],
+        mem: Addr,
+        done: impl Fn((usize, usize)) -> bool,
+    ) -> Option<Vec<(usize, usize)>> {
+        let usable = |cell: (usize, usize)| {
+            matches!(g.cell(cell), Cell::Empty)
+                && self
+                    .neighbours(cell)
+                    .iter()
+                    .all(|&n| self.gear_of(g, n).is_none_or(|m| m == mem))
+        };
+        let mut parent: HashMap<(usize, usize), Option<(usize, usize)>> = HashMap::new();
+        let mut todo = VecDeque::new();
+        for &c in connected {
+            for n in self.neighbours(c) {
+                if usable(n) && !parent.contains_key(&n) {
+                    parent.insert(n, None);
+                    todo.push_back(n);
+                }
+            }
+        }
+        while let Some(cell) = todo.pop_front() {
+            if done(cell) {
+                let mut path = vec![cell];
+                while let Some(prev) = parent[path.last().unwrap()] {
+                    path.push(prev);
+                }
+                return Some(path);
+            }
+            for n in self.neighbours(cell) {
+                if usable(n) && !parent.contains_key(&n) {
+                    parent.insert(n, Some(cell));
+                    todo.push_back(n);
+                }
+            }
+        }
+        None
+    }
+
+    /// Can a part at `cell` be placed where no ball reaches it: on the
+    /// lattice, not where balls enter, and with nothing above it that could
+    /// send a ball into it.
+    fn out_of_reach(&self, g: &Grid, (row, col): (usize, usize)) -> bool {
+        let entry = |color| {
+            let (r, c, _) = self.board.entry(color);
+            (r, c) == (row, col)
+        };
+        let above = |c: usize| {
+            matches!(
+                g.cell((row - 1, c)),
+                Cell::Empty | Cell::Gear(_) | Cell::MemBit(_)
+            )
+        };
+        Board::on_lattice(row, col)
+            && !entry(Color::Blue)
+            && !entry(Color::Red)
+            && (row == 0
+                || (col == 0 || above(col - 1)) && (col + 1 == self.board.cols() || above(col + 1)))
+    }
+
+    /// The memory address of the gear or gear bit at `cell`, if any.
+    fn gear_of(&self, g: &Grid, cell: (usize, usize)) -> Option<Addr> {
+        match *g.cell(cell) {
+            Cell::Gear(mem) | Cell::MemBit(mem) => Some(mem),
+            Cell::Node(i) if i < BLUE_LEVER => {
+                let mem = self.p.instr[i as usize].mem;
+                Some(mem).filter(|&m| self.geared[m as usize])
+            }
+            _ => None,
+        }
+    }
+
+    /// Orthogonally adjacent cells.
+    fn neighbours(&self, (row, col): (usize, usize)) -> Vec<(usize, usize)> {
+        let mut cells = vec![];
+        if row > 0 {
+            cells.push((row - 1, col));
+        }
+        if row + 1 < self.board.rows() {
+            cells.push((row + 1, col));
+        }
+        if col > 0 {
+            cells.push((row, col - 1));
+        }
+        if col + 1 < self.board.cols() {
+            cells.push((row, col + 1));
+        }
+        cells
+    }
+
+    /// Are there enough ramps and crossovers for the paths so far?
+    fn enough_track(&self, g: &Grid) -> bool {
+        let (ramps, crossovers, straight) = track_counts(g);
+        let inv = &self.inventory;
+        ramps <= inv.ramps
+            && crossovers <= inv.crossovers
+            && ramps + crossovers + straight <= inv.ramps.saturating_add(inv.crossovers)
+    }
+
+    fn board(&self, g: &Grid) -> Board {
+        let mut board = self.board.clone();
+        // straight paths take a ramp while there are enough, then a crossover
+        let mut spare_ramps = self.inventory.ramps - track_counts(g).0;
+        for row in 0..board.rows() {
+            for col in 0..board.cols() {
+                let part = match g.cell((row, col)) {
+                    Cell::Empty => None,
+                    Cell::Node(i) if *i < BLUE_LEVER => {
+                        let mem = self.p.instr[*i as usize].mem as usize;
+                        let value = self.p.mem[mem] ^ g.mirror[mem].unwrap();
+                        Some(if self.geared[mem] {
+                            Part::GearBit(value)
+                        } else {
+                            Part::Bit(value)
+                        })
+                    }
+                    Cell::Node(_) => Some(Part::Interceptor),
+                    Cell::Track(paths) => match track_kind(paths) {
+                        Track::Ramp(dir) => Some(Part::Ramp(dir)),
+                        Track::Crossover => Some(Part::Crossover),
+                        Track::Straight(_) if spare_ramps == 0 => Some(Part::Crossover),
+                        Track::Straight(dir) => {
+                            spare_ramps -= 1;
+                            Some(Part::Ramp(dir))
+                        }
+                    },
+                    Cell::Gear(_) => Some(Part::Gear),
+                    Cell::MemBit(mem) => {
+                        let mem = *mem as usize;
+                        Some(Part::GearBit(self.p.mem[mem] ^ g.mirror[mem].unwrap()))
+                    }
+                };
+                board.set(row, col, part);
+            }
+        }
+        board
+    }
+}
+
+/// The part a path cell needs.
+enum Track {
+    /// A path turns here.
+    Ramp(Dir),
+    /// Paths cross here.
+    Crossover,
+    /// Paths only go straight, in this direction: a ramp or a crossover.
+    Straight(Dir),
+}
+
+fn track_kind(paths: &[(Dir, Dir, Addr)]) -> Track {
+    let out = paths[0].1;
+    if paths.iter().any(|p| p.1 != out) {
+        Track::Crossover
+    } else if paths.iter().any(|p| p.0 != out) {
+        Track::Ramp(out)
+    } else {
+        Track::Straight(out)
+    }
+}
+
+/// Number of path cells that need a ramp, a crossover, and either.
+fn track_counts(g: &Grid) -> (usize, usize, usize) {
+    let mut counts = (0, 0, 0);
+    for cell in &g.cells {
+        if let Cell::Track(paths) = cell {
+            match track_kind(paths) {
+                Track::Ramp(_) => counts.0 += 1,
+                Track::Crossover => counts.1 += 1,
+                Track::Straight(_) => counts.2 += 1,
+            }
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const B: Addr = BLUE_LEVER;
+    const R: Addr = RED_LEVER;
+
+    #[test]
+    fn counter() {
+        let counter = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, 2, B), ijmp(2, B, B)],
+            mem: vec![false; 3],
+            ..State::default()
+        };
+        assert_eq!(
+            counter.to_board().unwrap().to_string(),
+            ". . . b . . . . . . .\n\
+             . . b . \\ . . . . . .\n\
+             . b . \\ . \\ . . . . .\n\
+             \\ . / . \\ . \\ . . . .\n\
+             . \\ . . . \\ . / . . .\n\
+             . . \\ . . . / . . . .\n\
+             . . . \\ . / . . . . .\n\
+             . . . . / . . . . . .\n\
+             . . . / . . . . . . .\n\
+             . . / . . . . . . . .\n\
+             . / . . . . . . . . .\n"
+        );
+    }
+
+    #[test]
+    fn gears_and_interceptors() {
+        // two coupled gear bits, and a bit that reads 1 but may be mirrored
+        let p = State {
+            entry: [0, 1],
+            instr: vec![ijmp(0, 2, R), ijmp(0, B, INTERC0), ijmp(2, B, INTERC0)],
+            mem: vec![false, false, true],
+            ..State::default()
+        };
+        let board = p.to_board().unwrap();
+        let count = |part: Part| board.parts().filter(|&(_, _, p)| p == part).count();
+        assert_eq!(count(Part::GearBit(false)) + count(Part::GearBit(true)), 2);
+        assert_eq!(count(Part::Bit(false)) + count(Part::Bit(true)), 1);
+        assert_eq!(count(Part::Interceptor), 1);
+        assert!(count(Part::Gear) >= 1);
+        assert_eq!(board.get(0, 3), Some(Part::GearBit(false)));
+    }
+
+    #[test]
+    fn errors() {
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 0, B)],
+            mem: vec![false],
+            ..State::default()
+        };
+        assert!(matches!(p.to_board(), Err(LayoutError::Invalid(_))));
+
+        let p = State {
+            entry: [0, 0],
+            instr: vec![ijmp(0, R, B)],
+            mem: vec![false],
+            ..State::default()
+        };
+        assert_eq!(
+            layout(&p, 1, 7).unwrap_err().to_string(),
+            "no layout found on a 7x1 board"
+        );
+        // mirrored: the bit reads 0, but points right
+        assert_eq!(
+            layout(&p, 3, 7).unwrap().to_string(),
+            ". \\ . . . / .\n\
+             . . \\ . / . .\n\
+             . . . B . . .\n"
+        );
+    }
+
+    #[test]
+    fn inventory() {
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, 2, B), ijmp(2, B, B)],
+            mem: vec![false; 3],
+            ..State::default()
+        };
+        let opts = LayoutOptions::default();
+        let few_bits = Inventory {
+            bits: 2,
+            ..Inventory::turing_tumble()
+        };
+        assert_eq!(
+            layout_with(&p, &opts.with_inventory(few_bits)).unwrap_err(),
+            LayoutError::NotEnoughParts {
+                part: "bits",
+                needed: 3,
+                available: 2
+            }
+        );
+
+        let board = layout_with(&p, &opts.with_inventory(Inventory::turing_tumble())).unwrap();
+        assert!(Inventory::turing_tumble().contains(&Inventory::of(&board)));
+
+        // straight paths use crossovers when ramps run out
+        let used = Inventory::of(&p.to_board().unwrap());
+        let few_ramps = Inventory {
+            ramps: used.ramps - 2,
+            crossovers: 2,
+            ..Inventory::unlimited()
+        };
+        let board = layout_with(&p, &opts.with_inventory(few_ramps)).unwrap();
+        assert!(few_ramps.contains(&Inventory::of(&board)));
+        let run = board.clone().run([7, 0], Color::Blue).unwrap();
+        assert_eq!(run.out_seq, p.with_balls([7, 0]).run().out_seq);
+
+        let no_track = Inventory {
+            ramps: 0,
+            crossovers: 0,
+            ..Inventory::unlimited()
+        };
+        assert_eq!(
+            layout_with(&p, &opts.with_inventory(no_track)).unwrap_err(),
+            LayoutError::NoFit { rows: 11, cols: 11 }
+        );
+    }
+
+    #[test]
+    fn reorder() {
+        let p = State {
+            entry: [0, 1],
+            instr: vec![ijmp(0, 2, 2), ijmp(1, B, B), ijmp(2, 3, R), ijmp(3, B, B)],
+            mem: vec![false; 4],
+            balls: [4, 4],
+            ..State::default()
+        };
+        let opts = LayoutOptions::default().with_size(5, 7);
+        assert!(layout_with(&p, &opts.with_reorder(false)).is_err());
+        let mut board = layout_with(&p, &opts).unwrap();
+        let run = board.run([4, 4], Color::Blue).unwrap();
+        assert_eq!(run.out_seq, p.run().out_seq);
+    }
+}
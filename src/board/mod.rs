@@ -0,0 +1,296 @@
+//! The physical board: parts on the peg grid.
+//!
+//! Coordinates are `(row, col)`, row 0 at the top. A ball only ever moves
+//! diagonally: a part at `(r, c)` sends it on to `(r + 1, c - 1)` (left) or
+//! `(r + 1, c + 1)` (right). So balls only visit the cells where `r + c` is
+//! odd; the other cells are only useful for gears.
+//!
+//! Blue balls enter the board at `(0, cols / 2 - 2)` moving right, red balls at
+//! `(0, cols / 2 + 2)` moving left. A ball that leaves the bottom row lands
+//! on the blue lever if it is in the left half of the board (column
+//! `<= cols / 2`), on the red lever otherwise. A ball that reaches an empty
+//! cell, or leaves the board sideways, falls off.
+
+mod compile;
+mod inventory;
+mod layout;
+mod parse;
+mod sim;
+
+pub use inventory::*;
+pub use layout::*;
+pub use sim::*;
+
+use crate::*;
+use std::fmt::{self, Write};
+
+/// The direction a ball is moving in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Dir {
+    Left,
+    Right,
+}
+
+/// A part that can be put on the board.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Part {
+    /// Sends the ball on in the given direction, whichever side it came from.
+    Ramp(Dir),
+    /// The ball continues in the direction it came from.
+    Crossover,
+    /// Flips, then sends the ball left if it is now pointing left (`false`),
+    /// right if it is now pointing right (`true`).
+    Bit(bool),
+    /// Like `Bit`, but also flips all gear bits connected to it by gears.
+    GearBit(bool),
+    /// Connects orthogonally adjacent gears and gear bits.
+    Gear,
+    /// Stops the ball, and the machine.
+    Interceptor,
+}
+
+impl Part {
+    pub fn as_char(&self) -> char {
+        match self {
+            Part::Ramp(Dir::Left) => '/',
+            Part::Ramp(Dir::Right) => '\\',
+            Part::Crossover => 'X',
+            Part::Bit(false) => 'b',
+            Part::Bit(true) => 'B',
+            Part::GearBit(false) => 'g',
+            Part::GearBit(true) => 'G',
+            Part::Gear => '*',
+            Part::Interceptor => 'I',
+        }
+    }
+
+    /// Inverse of `as_char`.
+    pub fn from_char(c: char) -> Option<Self> {
+        Some(match c {
+            '/' => Part::Ramp(Dir::Left),
+            '\\' => Part::Ramp(Dir::Right),
+            'X' => Part::Crossover,
+            'b' => Part::Bit(false),
+            'B' => Part::Bit(true),
+            'g' => Part::GearBit(false),
+            'G' => Part::GearBit(true),
+            '*' => Part::Gear,
+            'I' => Part::Interceptor,
+            _ => return None,
+        })
+    }
+
+    /// E.g. "gear bit".
+    pub fn name(&self) -> &'static str {
+        match self {
+            Part::Ramp(_) => "ramp",
+            Part::Crossover => "crossover",
+            Part::Bit(_) => "bit",
+            Part::GearBit(_) => "gear bit",
+            Part::Gear => "gear",
+            Part::Interceptor => "interceptor",
+        }
+    }
+
+    /// Gears and gear bits mesh with their orthogonal neighbours.
+    pub fn is_geared(&self) -> bool {
+        matches!(self, Part::GearBit(_) | Part::Gear)
+    }
+}
+
+/// A grid of parts, see the module documentation for the geometry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Board {
+    rows: usize,
+    cols: usize,
+    cells: Vec<Option<Part>>,
+}
+
+impl Board {
+    /// An empty board. `cols` must be 3 more than a multiple of 4 (e.g. 11),
+    /// so that the entrypoints are on the ball's lattice.
+    pub fn new(rows: usize, cols: usize) -> Self {
+        assert!(cols % 4 == 3, "unsupported board width: {}", cols);
+        Self {
+            rows,
+            cols,
+            cells: vec![None; rows * cols],
+        }
+    }
+
+    /// The Turing Tumble board: 11 columns, 11 rows.
+    pub fn standard() -> Self {
+        Self::new(11, 11)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<Part> {
+        self.cells[row * self.cols + col]
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, part: Option<Part>) {
+        self.cells[row * self.cols + col] = part
+    }
+
+    /// Can a ball reach cell `(row, col)`?
+    pub fn on_lattice(row: usize, col: usize) -> bool {
+        (row + col) % 2 == 1
+    }
+
+    /// The cell where balls of this color enter the board, and the direction
+    /// they are moving in.
+    pub fn entry(&self, color: Color) -> (usize, usize, Dir) {
+        match color {
+            Color::Blue => (0, self.cols / 2 - 2, Dir::Right),
+            Color::Red => (0, self.cols / 2 + 2, Dir::Left),
+        }
+    }
+
+    /// The lever that a ball leaving the bottom row towards `col` lands on.
+    pub fn lever(&self, col: usize) -> Addr {
+        if col <= self.cols / 2 {
+            BLUE_LEVER
+        } else {
+            RED_LEVER
+        }
+    }
+
+    /// The index of the interceptor at `(row, col)`, in reading order:
+    /// interceptor `k` is `INTERC0 + k`.
+    pub fn interceptor_index(&self, row: usize, col: usize) -> usize {
+        self.parts()
+            .take_while(|&(r, c, _)| (r, c) < (row, col))
+            .filter(|&(_, _, p)| p == Part::Interceptor)
+            .count()
+    }
+
+    /// All gears and gear bits connected to the one at `(row, col)`
+    /// (including itself), in reading order.
+    pub fn gear_train(&self, row: usize, col: usize) -> Vec<(usize, usize)> {
+        let mut train = vec![(row, col)];
+        let mut todo = vec![(row, col)];
+        while let Some((r, c)) = todo.pop() {
+            let neighbours = [
+                (r.wrapping_sub(1), c),
+                (r + 1, c),
+                (r, c.wrapping_sub(1)),
+                (r, c + 1),
+            ];
+            for (r, c) in neighbours {
+                if r < self.rows
+                    && c < self.cols
+                    && self.get(r, c).is_some_and(|p| p.is_geared())
+                    && !train.contains(&(r, c))
+                {
+                    train.push((r, c));
+                    todo.push((r, c));
+                }
+            }
+        }
+        train.sort_unstable();
+        train
+    }
+
+    /// Follow a ball entering `(row, col)` moving in direction `dir` through
+    /// ramps and crossovers, until it reaches another part or leaves the board.
+    fn slide(&self, mut row: usize, mut col: isize, mut dir: Dir) -> Landing {
+        loop {
+            if col < 0 || col >= self.cols as isize {
+                return Landing::FellOff(row, col);
+            }
+            if row == self.rows {
+                return Landing::Lever(self.lever(col as usize));
+            }
+            match self.get(row, col as usize) {
+                Some(Part::Ramp(d)) => dir = d,
+                Some(Part::Crossover) => (),
+                None | Some(Part::Gear) => return Landing::FellOff(row, col),
+                Some(part) => return Landing::Part(row, col as usize, part),
+            }
+            row += 1;
+            col += if dir == Dir::Left { -1 } else { 1 };
+        }
+    }
+
+    /// All parts, with their position, in reading order.
+    pub fn parts(&self) -> impl Iterator<Item = (usize, usize, Part)> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, part)| part.map(|part| (i / self.cols, i % self.cols, part)))
+    }
+}
+
+/// Where a ball ends up, see `Board::slide`.
+enum Landing {
+    /// Below the bottom row, on this lever.
+    Lever(Addr),
+    /// An empty cell or a gear, or outside the board.
+    FellOff(usize, isize),
+    /// A bit, gear bit or interceptor.
+    Part(usize, usize, Part),
+}
+
+/// Error running or compiling a `Board`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BoardError {
+    /// The ball reached an empty cell or a gear, or left the board sideways.
+    FellOff { row: usize, col: isize, ball: usize },
+    /// `State` supports up to 3 interceptors.
+    TooManyInterceptors(usize),
+    /// `State` supports up to 128 instructions.
+    TooManyBits(usize),
+    /// Gear bits connected by gears must point in the same direction.
+    GearsDisagree { row: usize, col: usize },
+}
+
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BoardError::FellOff { row, col, ball } => write!(
+                f,
+                "ball {} fell off the board at row {}, col {}",
+                ball, row, col
+            ),
+            BoardError::TooManyInterceptors(n) => {
+                write!(f, "{} interceptors, at most 3 are supported", n)
+            }
+            BoardError::TooManyBits(n) => write!(
+                f,
+                "{} bits and gear bits, at most {} are supported",
+                n, BLUE_LEVER
+            ),
+            BoardError::GearsDisagree { row, col } => write!(
+                f,
+                "gear bit at row {}, col {} points in a different direction than \
+                 the gear bits connected to it",
+                row, col
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BoardError {}
+
+/// One line per row, e.g. `. . . b . . . . . . .`, `.` for empty cells.
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                if col > 0 {
+                    f.write_char(' ')?;
+                }
+                f.write_char(self.get(row, col).map_or('.', |p| p.as_char()))?;
+            }
+            f.write_char('\n')?;
+        }
+        Ok(())
+    }
+}
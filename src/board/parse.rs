@@ -0,0 +1,216 @@
+use super::*;
+use std::str::FromStr;
+
+/// Parses the text format emitted by `Board`'s `Display` impl: one line per
+/// row, one character per cell (`.` for an empty cell, see `Part::as_char`
+/// for the others). Whitespace between cells is optional, and `#` starts a
+/// comment. E.g. a 2-bit counter, on a board 7 cells wide:
+///     . b . . . . .   # blue balls enter at col 1
+///     b . \ . . . .
+///     . / . / . . .
+///
+/// Parts that balls can never reach (anything but a gear on a cell with
+/// `row + col` even) are rejected, and so are boards that cannot be compiled
+/// into a `State` (see `Board::to_state`), e.g. because gear bits connected
+/// by gears point in different directions.
+impl FromStr for Board {
+    type Err = ParseError;
+
+    fn from_str(src: &str) -> Result<Self, ParseError> {
+        let err = |line, col, msg: String| ParseError { line, col, msg };
+
+        // each cell with its column in the source
+        type Row = Vec<(Option<Part>, usize)>;
+        let mut rows: Vec<(usize, Row)> = vec![];
+        for (l, line) in src.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or("");
+            let mut row = vec![];
+            for (c, ch) in code.chars().enumerate() {
+                let part = match ch {
+                    ch if ch.is_whitespace() => continue,
+                    '.' => None,
+                    ch => Some(Part::from_char(ch).ok_or_else(|| {
+                        err(
+                            l + 1,
+                            c + 1,
+                            format!(
+                                "unknown part `{}`, expected one of `\\ / X b B g G * I .`",
+                                ch
+                            ),
+                        )
+                    })?),
+                };
+                row.push((part, c + 1));
+            }
+            if row.is_empty() {
+                continue;
+            }
+            if let Some((_, first)) = rows.first() {
+                if row.len() != first.len() {
+                    return Err(err(
+                        l + 1,
+                        1,
+                        format!(
+                            "row has {} cells, the first row has {}",
+                            row.len(),
+                            first.len()
+                        ),
+                    ));
+                }
+            }
+            rows.push((l + 1, row));
+        }
+
+        let cols = match rows.first() {
+            None => return Err(err(1, 1, "empty board".into())),
+            Some((_, row)) => row.len(),
+        };
+        if cols % 4 != 3 {
+            return Err(err(
+                rows[0].0,
+                1,
+                format!(
+                    "unsupported board width {}, must be 3 more than a multiple of 4 (e.g. 11)",
+                    cols
+                ),
+            ));
+        }
+
+        let mut board = Board::new(rows.len(), cols);
+        for (r, (line, row)) in rows.iter().enumerate() {
+            for (c, &(part, col)) in row.iter().enumerate() {
+                if let Some(part) = part {
+                    if part != Part::Gear && !Board::on_lattice(r, c) {
+                        return Err(err(
+                            *line,
+                            col,
+                            format!(
+                                "{} at row {}, col {} is not on the path of the balls",
+                                part.name(),
+                                r,
+                                c
+                            ),
+                        ));
+                    }
+                }
+                board.set(r, c, part);
+            }
+        }
+
+        // report compilation errors at the offending part
+        let at = |row: usize, col: usize, msg: String| {
+            let (line, cells) = &rows[row];
+            err(*line, cells[col].1, msg)
+        };
+        let nth = |n: usize, pred: &dyn Fn(Part) -> bool| {
+            let (r, c, _) = board.parts().filter(|&(_, _, p)| pred(p)).nth(n).unwrap();
+            (r, c)
+        };
+        match board.to_state() {
+            Ok(_) => Ok(board),
+            Err(e @ BoardError::GearsDisagree { row, col }) => Err(at(row, col, e.to_string())),
+            Err(e @ BoardError::TooManyInterceptors(_)) => {
+                let (r, c) = nth(3, &|p| p == Part::Interceptor);
+                Err(at(r, c, e.to_string()))
+            }
+            Err(e @ BoardError::TooManyBits(_)) => {
+                let bit = |p| matches!(p, Part::Bit(_) | Part::GearBit(_));
+                let (r, c) = nth(BLUE_LEVER as usize, &bit);
+                Err(at(r, c, e.to_string()))
+            }
+            Err(e @ BoardError::FellOff { .. }) => unreachable!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const B: Addr = BLUE_LEVER;
+
+    #[test]
+    fn parse() {
+        let board: Board = "\
+            . b . . . . .   # blue balls enter at col 1
+            b . \\ . . . .
+            . / . / . . .
+        "
+        .parse()
+        .unwrap();
+        let p = board.to_state().unwrap();
+        assert_eq!(p.entry, [0, FALL]);
+        assert_eq!(p.instr[0].to_string(), "ijmp 0 1 B");
+        assert_eq!(p.instr[1].to_string(), "ijmp 1 FALL B");
+        let out = p.with_balls([3, 0]).run();
+        assert_eq!(out.register(0..2), 3);
+
+        // whitespace between cells is optional
+        let compact: Board = ".b.....\nb.\\....\n././...\n".parse().unwrap();
+        assert_eq!(compact, board);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let p = State {
+            entry: [0, 1],
+            instr: vec![ijmp(0, 2, B), ijmp(0, B, INTERC1), ijmp(2, INTERC0, B)],
+            mem: vec![false, false, true],
+            ..State::default()
+        };
+        let board = p.to_board().unwrap();
+        assert_eq!(board.to_string().parse::<Board>(), Ok(board));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |src: &str| {
+            let err = src.parse::<Board>().unwrap_err();
+            (err.line, err.col, err.msg)
+        };
+        assert_eq!(
+            error(". b . q . . ."),
+            (
+                1,
+                7,
+                "unknown part `q`, expected one of `\\ / X b B g G * I .`".into()
+            )
+        );
+        assert_eq!(
+            error(". b . . . . .\n. . . ."),
+            (2, 1, "row has 4 cells, the first row has 7".into())
+        );
+        assert_eq!(error("\n# nothing\n"), (1, 1, "empty board".into()));
+        assert_eq!(
+            error(". . . ."),
+            (
+                1,
+                1,
+                "unsupported board width 4, must be 3 more than a multiple of 4 (e.g. 11)".into()
+            )
+        );
+        assert_eq!(
+            error(". b . . . . .\n. \\ . . . . ."),
+            (
+                2,
+                3,
+                "ramp at row 1, col 1 is not on the path of the balls".into()
+            )
+        );
+        assert_eq!(
+            error(". g * G . . .\n. . . . . . ."),
+            (
+                1,
+                7,
+                "gear bit at row 0, col 3 points in a different direction than \
+                 the gear bits connected to it"
+                    .into()
+            )
+        );
+        assert_eq!(
+            error(". I . I . I . I . . .\n. . . . . . . . . . ."),
+            (1, 15, "4 interceptors, at most 3 are supported".into())
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use super::*;
+
+/// Result of `Board::run`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BoardRun {
+    /// Balls remaining, blue and red.
+    pub balls: [u8; 2],
+    /// The colors of the balls that reached a lever, in order.
+    pub out_seq: Vec<Color>,
+    /// The interceptor (by index, see `Board::interceptor_index`) that
+    /// caught a ball and halted the machine, and the ball's color.
+    pub intercepted: Option<(usize, Color)>,
+}
+
+impl Board {
+    /// Simulate marbles falling through the board, starting with the start
+    /// button `start`, until the machine runs out of balls or a ball is
+    /// intercepted. Bits flip as balls pass them (and so do all gear bits
+    /// connected to a gear bit), so afterwards the board holds the final
+    /// orientation of each bit.
+    pub fn run(&mut self, balls: [u8; 2], start: Color) -> Result<BoardRun, BoardError> {
+        let mut run = BoardRun {
+            balls,
+            ..BoardRun::default()
+        };
+        let mut color = start;
+        for ball in 0.. {
+            if run.balls[usize::from(color)] == 0 {
+                break;
+            }
+            run.balls[usize::from(color)] -= 1;
+            let (mut row, col, dir) = self.entry(color);
+            let (mut col, mut dir) = (col as isize, dir);
+            loop {
+                match self.slide(row, col, dir) {
+                    Landing::Lever(lever) => {
+                        run.out_seq.push(color);
+                        color = if lever == BLUE_LEVER {
+                            Color::Blue
+                        } else {
+                            Color::Red
+                        };
+                        break;
+                    }
+                    Landing::FellOff(row, col) => {
+                        return Err(BoardError::FellOff { row, col, ball });
+                    }
+                    Landing::Part(r, c, Part::Interceptor) => {
+                        run.intercepted = Some((self.interceptor_index(r, c), color));
+                        return Ok(run);
+                    }
+                    Landing::Part(r, c, part) => {
+                        let right = match part {
+                            Part::Bit(value) => {
+                                self.set(r, c, Some(Part::Bit(!value)));
+                                !value
+                            }
+                            Part::GearBit(value) => {
+                                for (r, c) in self.gear_train(r, c) {
+                                    if let Some(Part::GearBit(v)) = self.get(r, c) {
+                                        self.set(r, c, Some(Part::GearBit(!v)));
+                                    }
+                                }
+                                !value
+                            }
+                            _ => unreachable!("slide stops at bits and interceptors"),
+                        };
+                        row = r + 1;
+                        (col, dir) = if right {
+                            (c as isize + 1, Dir::Right)
+                        } else {
+                            (c as isize - 1, Dir::Left)
+                        };
+                    }
+                }
+            }
+        }
+        Ok(run)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use Color::*;
+
+    const B: Addr = BLUE_LEVER;
+    const R: Addr = RED_LEVER;
+
+    /// Run `p`, its layout, and the layout compiled back to a `State`,
+    /// and check that they agree.
+    fn cross_check(p: &State) {
+        let mut board = p.to_board().unwrap();
+        let compiled = board.to_state().unwrap();
+        let expected = p.clone().run();
+        let out = compiled
+            .clone()
+            .with_balls(p.balls)
+            .with_start(p.start_button)
+            .run();
+        assert_eq!(out.out_seq, expected.out_seq, "{}", board);
+        assert_eq!(out.intercept, expected.intercept);
+
+        let run = board.run(p.balls, p.start_button).unwrap();
+        assert_eq!(run.out_seq, expected.out_seq, "{}", board);
+        let intercept = run
+            .intercepted
+            .map(|(i, color)| (INTERC0 + i as Addr, color));
+        let expected_intercept = (INTERC0..=INTERC2)
+            .zip(expected.intercept)
+            .find_map(|(i, color)| color.map(|color| (i, color)));
+        assert_eq!(intercept, expected_intercept);
+        // the final orientation of the bits is the final memory of the compiled program
+        let bits = board
+            .parts()
+            .filter(|(_, _, p)| matches!(p, Part::Bit(_) | Part::GearBit(_)));
+        for ((_, _, part), instr) in bits.zip(&compiled.instr) {
+            let value = matches!(part, Part::Bit(true) | Part::GearBit(true));
+            assert_eq!(value, out.mem[instr.mem as usize]);
+        }
+    }
+
+    #[test]
+    fn counter() {
+        let counter = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, 2, B), ijmp(2, B, B)],
+            mem: vec![false; 3],
+            balls: [6, 0],
+            ..State::default()
+        };
+        cross_check(&counter);
+
+        let mut board = counter.to_board().unwrap();
+        let run = board.run([6, 0], Blue).unwrap();
+        assert_eq!(run.balls, [0, 0]);
+        assert_eq!(run.out_seq, vec![Blue; 6]);
+        // 6 = 0b110, least significant bit first
+        let bits: Vec<Part> = board
+            .parts()
+            .map(|(_, _, p)| p)
+            .filter(|p| !matches!(p, Part::Ramp(_) | Part::Crossover))
+            .collect();
+        assert_eq!(
+            bits,
+            vec![Part::Bit(false), Part::Bit(true), Part::Bit(true)]
+        );
+    }
+
+    #[test]
+    fn gears_and_interceptors() {
+        // a gear bit sets the other one, which then sends the ball to an interceptor
+        let p = State {
+            entry: [0, 1],
+            instr: vec![ijmp(0, 1, R), ijmp(0, R, INTERC0)],
+            mem: vec![false],
+            balls: [3, 3],
+            ..State::default()
+        };
+        cross_check(&p);
+        cross_check(&p.with_start(Red));
+        cross_check(&p.with_mem(vec![true]));
+
+        // the sequence solution from the README: B RR BBB RRRR BBBBB
+        let p = State {
+            entry: [2, 0],
+            instr: vec![
+                ijmp(0, 1, R),
+                ijmp(1, R, 2),
+                ijmp(2, B, 3),
+                ijmp(3, B, 4),
+                ijmp(4, R, 5),
+                ijmp(5, B, R),
+            ],
+            mem: vec![false; 6],
+            balls: [9, 6],
+            ..State::default()
+        };
+        assert_eq!(p.clone().run().out_seq.len(), 15);
+        cross_check(&p);
+    }
+
+    #[test]
+    fn memory_only_gear_bit() {
+        // instruction 1 uses mem[2], which belongs to no instruction: it is
+        // built as a gear bit of its own, out of reach of the balls
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(2, B, B)],
+            mem: vec![false; 3],
+            balls: [3, 0],
+            ..State::default()
+        };
+        cross_check(&p);
+
+        let mut board = p.to_board().unwrap();
+        let gear_bits = |board: &Board| {
+            board
+                .parts()
+                .filter_map(|(_, _, part)| match part {
+                    Part::GearBit(value) => Some(value),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(gear_bits(&board), vec![false, false]);
+        board.run(p.balls, p.start_button).unwrap();
+        assert!(p.clone().run().mem[2]);
+        assert_eq!(gear_bits(&board), vec![true, true]);
+    }
+
+    #[test]
+    fn fell_off() {
+        let mut board = Board::standard();
+        board.set(0, 3, Some(Part::Ramp(Dir::Left)));
+        board.set(1, 2, Some(Part::Gear));
+        assert_eq!(
+            board.run([1, 1], Blue),
+            Err(BoardError::FellOff {
+                row: 1,
+                col: 2,
+                ball: 0
+            })
+        );
+        let mut board = Board::standard();
+        assert_eq!(
+            board.run([0, 1], Blue),
+            Ok(BoardRun {
+                balls: [0, 1],
+                ..BoardRun::default()
+            })
+        );
+    }
+}
@@ -0,0 +1,330 @@
+use super::*;
+
+/// The instructions reachable from the entrypoints, in canonical order.
+///
+/// Two programs that only differ by a renumbering of their instructions
+/// have the same canonical order (up to that renumbering), so a program is
+/// in canonical form if its canonical order is simply `0, 1, 2, ...`.
+///
+/// The order is topological (jumps only go forward), and ties are broken
+/// by when an instruction was first referenced: first the blue and red
+/// entrypoints, then jmp0 and jmp1 of each instruction in canonical order.
+pub fn canonical_order(p: &State) -> Vec<Addr> {
+    let n = p.instr.len();
+    let is_instr = |addr: Addr| (addr as usize) < n;
+
+    // instructions reachable from the entrypoints
+    let mut reachable = vec![false; n];
+    let mut todo: Vec<Addr> = p.entry.to_vec();
+    while let Some(addr) = todo.pop() {
+        if is_instr(addr) && !reachable[addr as usize] {
+            reachable[addr as usize] = true;
+            let instr = p.instr[addr as usize];
+            todo.extend([instr.jmp0, instr.jmp1]);
+        }
+    }
+
+    // number of jumps into each instruction, from reachable ones:
+    // unreachable instructions are never done, so they must not count
+    let mut preds = vec![0; n];
+    for (i, instr) in p.instr.iter().enumerate() {
+        if !reachable[i] {
+            continue;
+        }
+        for jmp in [instr.jmp0, instr.jmp1] {
+            if is_instr(jmp) {
+                preds[jmp as usize] += 1;
+            }
+        }
+    }
+
+    // time of first reference
+    let mut seen = vec![usize::MAX; n];
+    let mut time = 0;
+    let mut see = |addr: Addr, seen: &mut Vec<usize>| {
+        if is_instr(addr) && seen[addr as usize] == usize::MAX {
+            seen[addr as usize] = time;
+        }
+        time += 1;
+    };
+    see(p.entry[0], &mut seen);
+    see(p.entry[1], &mut seen);
+
+    let mut order = Vec::with_capacity(n);
+    let mut done = vec![false; n];
+    loop {
+        // ready: all predecessors done. Pick the one seen first.
+        let next = (0..n)
+            .filter(|&i| !done[i] && preds[i] == 0 && seen[i] != usize::MAX)
+            .min_by_key(|&i| seen[i]);
+        let i = match next {
+            None => return order,
+            Some(i) => i,
+        };
+        done[i] = true;
+        order.push(i as Addr);
+        for jmp in [p.instr[i].jmp0, p.instr[i].jmp1] {
+            see(jmp, &mut seen);
+            if is_instr(jmp) {
+                preds[jmp as usize] -= 1;
+            }
+        }
+    }
+}
+
+/// The interceptors used by `p`, in order of first reference
+/// (entrypoints first, then the instructions in canonical `order`).
+fn interceptor_order(p: &State, order: &[Addr]) -> Vec<Addr> {
+    let jumps = order
+        .iter()
+        .flat_map(|&i| [p.instr[i as usize].jmp0, p.instr[i as usize].jmp1]);
+    let mut used = vec![];
+    for addr in p.entry.iter().copied().chain(jumps) {
+        if (INTERC0..=INTERC2).contains(&addr) && !used.contains(&addr) {
+            used.push(addr)
+        }
+    }
+    used
+}
+
+/// The interceptors in `ext_addr`, in ascending order.
+fn allowed_interceptors(ext_addr: &[Addr]) -> Vec<Addr> {
+    let mut allowed: Vec<Addr> = ext_addr
+        .iter()
+        .copied()
+        .filter(|a| (INTERC0..=INTERC2).contains(a))
+        .collect();
+    allowed.sort_unstable();
+    allowed
+}
+
+/// Is `p` the canonical representative among all programs that only differ
+/// by a renumbering of instructions, or of the interceptors in `ext_addr`?
+/// I.e.: all instructions are reachable, they are in canonical order,
+/// and the interceptors are used in ascending order.
+pub fn is_canonical(p: &State, ext_addr: &[Addr]) -> bool {
+    let order = canonical_order(p);
+    order.len() == p.instr.len()
+        && order.iter().enumerate().all(|(i, &o)| i == o as usize)
+        && interceptor_order(p, &order)
+            .iter()
+            .zip(allowed_interceptors(ext_addr))
+            .all(|(&used, allowed)| used == allowed)
+}
+
+/// Can `p` still be canonical (see `is_canonical`) when only its entrypoints
+/// and instructions `0..=last` are known? Jumps only go forward, so this
+/// already decides the interceptors used first, and whether `canonical_order`
+/// would pick instruction `last + 1` before one of `0..=last`. Used to only
+/// generate canonical programs, rather than filter them at the end.
+pub(crate) fn is_canonical_prefix(p: &State, last: Addr, ext_addr: &[Addr]) -> bool {
+    let decided = &p.instr[..=last as usize];
+    // references in the order `canonical_order` sees them, if `p` is canonical
+    let refs = || {
+        p.entry
+            .iter()
+            .copied()
+            .chain(decided.iter().flat_map(|i| [i.jmp0, i.jmp1]))
+    };
+
+    // the n-th interceptor used must be the n-th smallest one in `ext_addr`
+    let mut used = 0;
+    for (pos, addr) in refs().enumerate() {
+        if (INTERC0..=INTERC2).contains(&addr) && !refs().take(pos).any(|a| a == addr) {
+            let smaller = ext_addr.iter().filter(|&&a| (INTERC0..addr).contains(&a));
+            if smaller.count() != used {
+                return false;
+            }
+            used += 1;
+        }
+    }
+
+    // Instruction `next` is ready (all its predecessors done) as soon as no
+    // undone instruction jumps to it. If it was referenced before instruction
+    // `k`, it must not be ready when `k` is picked.
+    let next = last + 1;
+    if next as usize == p.instr.len() {
+        return true;
+    }
+    let first_ref = |addr: Addr| refs().position(|a| a == addr);
+    let next_ref = match first_ref(next) {
+        None => return true, // unreachable, not decided here
+        Some(pos) => pos,
+    };
+    match (0..=last).rev().find(|&k| first_ref(k) > Some(next_ref)) {
+        None => true,
+        Some(k) => decided[k as usize..]
+            .iter()
+            .any(|i| i.jmp0 == next || i.jmp1 == next),
+    }
+}
+
+/// Renumber `p` into the canonical form that `is_canonical` accepts:
+/// instructions in canonical order, interceptors from `ext_addr` in order of
+/// use, and memory in order of use. Unreachable instructions and memory that
+/// they do not use are removed.
+pub fn canonical_form(p: &State, ext_addr: &[Addr]) -> State {
+    let order = canonical_order(p);
+
+    let mut new_instr = vec![FALL; p.instr.len()];
+    for (new, &old) in order.iter().enumerate() {
+        new_instr[old as usize] = new as Addr;
+    }
+    let new_interc: Vec<(Addr, Addr)> = interceptor_order(p, &order)
+        .into_iter()
+        .zip(allowed_interceptors(ext_addr))
+        .collect();
+    let addr = |a: Addr| -> Addr {
+        if (a as usize) < p.instr.len() {
+            new_instr[a as usize]
+        } else {
+            match new_interc.iter().find(|(old, _)| *old == a) {
+                Some(&(_, new)) => new,
+                None => a,
+            }
+        }
+    };
+
+    let mut new_mem: Vec<Addr> = vec![];
+    let mut mem = vec![];
+    let mut instr = vec![];
+    for &old in &order {
+        let old = p.instr[old as usize];
+        let m = match new_mem.iter().position(|&m| m == old.mem) {
+            Some(m) => m,
+            None => {
+                new_mem.push(old.mem);
+                mem.push(p.mem[old.mem as usize]);
+                new_mem.len() - 1
+            }
+        };
+        instr.push(ijmp(m as Addr, addr(old.jmp0), addr(old.jmp1)));
+    }
+
+    State {
+        entry: [addr(p.entry[0]), addr(p.entry[1])],
+        mem,
+        instr,
+        ..p.clone()
+    }
+}
+
+impl State {
+    /// The same machine in canonical form: unreachable instructions and memory
+    /// that is not used by any instruction are removed, instructions are
+    /// renumbered in canonical order (see `canonical_order`), and memory in
+    /// order of use. Interceptors are kept as they are.
+    ///
+    /// Programs that only differ by dead code or by numbering have the same
+    /// canonical form. Note that memory addresses change, so registers
+    /// (`State::register`) may no longer be where a puzzle expects them.
+    pub fn canonicalize(&self) -> State {
+        canonical_form(self, &[])
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const B: Addr = BLUE_LEVER;
+    const R: Addr = RED_LEVER;
+
+    #[test]
+    fn order() {
+        // 0 -> (2, 1), 1 -> 2: 1 must come before 2, although 2 is seen first.
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 2, 1), ijmp(1, 2, B), ijmp(2, B, R)],
+            mem: vec![false; 3],
+            ..State::default()
+        };
+        assert_eq!(canonical_order(&p), vec![0, 1, 2]);
+        assert!(is_canonical(&p, &[B, R]));
+
+        // swapping two independent instructions is not canonical
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 2, 1), ijmp(1, B, B), ijmp(2, R, R)],
+            mem: vec![false; 3],
+            ..State::default()
+        };
+        assert_eq!(canonical_order(&p), vec![0, 2, 1]);
+        assert!(!is_canonical(&p, &[B, R]));
+        assert!(is_canonical(&canonical_form(&p, &[B, R]), &[B, R]));
+
+        // unreachable instruction
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, B, B), ijmp(1, B, B)],
+            mem: vec![false; 2],
+            ..State::default()
+        };
+        assert_eq!(canonical_order(&p), vec![0]);
+        assert!(!is_canonical(&p, &[B]));
+        assert_eq!(canonical_form(&p, &[B]).instr.len(), 1);
+
+        // an unreachable instruction jumping into reachable ones
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 2, 2), ijmp(1, 2, 2), ijmp(2, B, B)],
+            mem: vec![false; 3],
+            ..State::default()
+        };
+        assert_eq!(canonical_order(&p), vec![0, 2]);
+    }
+
+    #[test]
+    fn interceptors() {
+        let p = State {
+            entry: [0, INTERC1],
+            instr: vec![ijmp(0, INTERC0, INTERC1)],
+            mem: vec![false],
+            ..State::default()
+        };
+        let ext_addr = [INTERC0, INTERC1];
+        assert!(!is_canonical(&p, &ext_addr));
+        let q = canonical_form(&p, &ext_addr);
+        assert_eq!(q.entry, [0, INTERC0]);
+        assert_eq!((q.instr[0].jmp0, q.instr[0].jmp1), (INTERC1, INTERC0));
+        assert!(is_canonical(&q, &ext_addr));
+    }
+
+    #[test]
+    fn canonicalize() {
+        let counter = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, 2, B), ijmp(2, B, B)],
+            mem: vec![false; 3],
+            ..State::default()
+        };
+        // same counter, with a dead instruction, in a different order
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![
+                ijmp(0, 2, B),
+                ijmp(1, R, INTERC0),
+                ijmp(2, 3, B),
+                ijmp(3, B, B),
+            ],
+            mem: vec![false; 4],
+            ..State::default()
+        };
+        assert_eq!(p.canonicalize().to_string(), counter.to_string());
+        assert_eq!(counter.canonicalize().to_string(), counter.to_string());
+
+        // a dead instruction jumping into live code is removed, the live code is kept
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 2, 2), ijmp(1, 2, 2), ijmp(2, B, B)],
+            mem: vec![false; 3],
+            balls: [3, 0],
+            ..State::default()
+        };
+        let q = p.canonicalize();
+        assert_eq!(q.instr.len(), 2);
+        assert_eq!(q.try_run().unwrap().output_str(), "bbb");
+        assert_eq!(p.try_run().unwrap().output_str(), "bbb");
+    }
+}
@@ -1,8 +1,9 @@
 use std::fmt;
 use std::fmt::Write;
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub enum Color {
+    #[default]
     Blue = 0,
     Red = 1,
 }
@@ -26,16 +27,19 @@ impl Color {
             Self::Red => 'r',
         }
     }
-}
 
-impl Into<usize> for Color {
-    fn into(self) -> usize {
-        self as usize
+    /// Inverse of `as_char`.
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'b' => Some(Self::Blue),
+            'r' => Some(Self::Red),
+            _ => None,
+        }
     }
 }
 
-impl Default for Color {
-    fn default() -> Self {
-        Color::Blue
+impl From<Color> for usize {
+    fn from(c: Color) -> usize {
+        c as usize
     }
 }
@@ -0,0 +1,266 @@
+use super::*;
+use std::ops::Range;
+
+/// Maximum number of instructions, and of memory bits, of a `Compact` machine.
+pub const COMPACT_CAPACITY: usize = 64;
+
+/// Words of `Compact::out`: enough for all balls (at most 2 * 255), and
+/// a few that were output before.
+const OUT_WORDS: usize = 8;
+
+/// A `State` that fits in a fixed amount of memory, so that copying and
+/// running it never allocates: memory is a `u64` bitset, the instructions
+/// an inline array, and the output sequence a bitset (1: red) and a length.
+/// Used to check `Puzzle` cases in the search for solutions.
+///
+/// Programs with more than `COMPACT_CAPACITY` instructions or memory bits do
+/// not fit (see `State::to_compact`).
+#[derive(Clone, Copy, Debug)]
+pub struct Compact {
+    pub balls: [u8; 2],
+    pub start_button: Color,
+    pub entry: [Addr; 2],
+    n_instr: u8,
+    instr: [Instr; COMPACT_CAPACITY],
+    n_mem: u8,
+    mem: u64,
+    pub intercept: [Option<Color>; 3],
+    pub undecided: Option<(Addr, bool)>,
+    out_len: u16,
+    out: [u64; OUT_WORDS],
+}
+
+impl State {
+    /// This state as a `Compact` machine, if it fits: with its output
+    /// sequence, and all the balls it may still output.
+    pub fn to_compact(&self) -> Option<Compact> {
+        let out_len = self.out_seq.len() + self.balls[0] as usize + self.balls[1] as usize;
+        if self.instr.len() > COMPACT_CAPACITY
+            || self.mem.len() > COMPACT_CAPACITY
+            || out_len > OUT_WORDS * 64
+        {
+            return None;
+        }
+        let mut instr = [ijmp(0, FALL, FALL); COMPACT_CAPACITY];
+        instr[..self.instr.len()].copy_from_slice(&self.instr);
+        let mut c = Compact {
+            balls: self.balls,
+            start_button: self.start_button,
+            entry: self.entry,
+            n_instr: self.instr.len() as u8,
+            instr,
+            n_mem: self.mem.len() as u8,
+            mem: 0,
+            intercept: self.intercept,
+            undecided: self.undecided,
+            out_len: 0,
+            out: [0; OUT_WORDS],
+        };
+        for (addr, &bit) in self.mem.iter().enumerate() {
+            c.mem |= (bit as u64) << addr;
+        }
+        for &color in &self.out_seq {
+            c.push_out(color);
+        }
+        Some(c)
+    }
+}
+
+impl Compact {
+    /// Back to a `State`.
+    pub fn to_state(&self) -> State {
+        State {
+            balls: self.balls,
+            start_button: self.start_button,
+            entry: self.entry,
+            mem: (0..self.n_mem).map(|a| self.bit(a)).collect(),
+            instr: self.instr().to_vec(),
+            intercept: self.intercept,
+            undecided: self.undecided,
+            out_seq: self.out_seq().collect(),
+        }
+    }
+
+    pub fn instr(&self) -> &[Instr] {
+        &self.instr[..self.n_instr as usize]
+    }
+
+    /// Like `State::try_run`, without allocating.
+    pub fn try_run(&mut self) -> Result<(), RunError> {
+        let (mut pc, mut color) = match self.release(self.start_button) {
+            None => return Ok(()),
+            Some(pc_color) => pc_color,
+        };
+        // last instruction executed, and the jump it took
+        let mut from = (pc, false);
+        loop {
+            while pc < BLUE_LEVER {
+                let ball = self.out_len as usize;
+                if pc >= self.n_instr {
+                    return Err(RunError::InstrOutOfRange { pc, ball });
+                }
+                let Instr { mem, jmp0, jmp1 } = self.instr[pc as usize];
+                if mem >= self.n_mem {
+                    return Err(RunError::MemOutOfRange { pc, mem, ball });
+                }
+                self.mem ^= 1 << mem;
+                let value = self.bit(mem);
+                from = (pc, value);
+                let target = if value { jmp1 } else { jmp0 };
+                if target <= pc {
+                    return Err(RunError::BackwardJump { pc, target, ball });
+                }
+                pc = target;
+            }
+            match pc {
+                BLUE_LEVER | RED_LEVER => {
+                    self.push_out(color);
+                    let next = if pc == BLUE_LEVER {
+                        Color::Blue
+                    } else {
+                        Color::Red
+                    };
+                    match self.release(next) {
+                        None => return Ok(()),
+                        Some(pc_color) => (pc, color) = pc_color,
+                    }
+                }
+                INTERC0..=INTERC2 => {
+                    self.intercept[(pc - INTERC0) as usize] = Some(color);
+                    return Ok(());
+                }
+                UNDECIDED => {
+                    self.undecided = Some(from);
+                    return Ok(());
+                }
+                _ => {
+                    let ball = self.out_len as usize;
+                    return Err(RunError::FellOff { pc, ball });
+                }
+            }
+        }
+    }
+
+    fn release(&mut self, color: Color) -> Option<(Addr, Color)> {
+        let balls = &mut self.balls[color as usize];
+        if *balls == 0 {
+            return None;
+        }
+        *balls -= 1;
+        Some((self.entry[color as usize], color))
+    }
+
+    fn push_out(&mut self, color: Color) {
+        let i = self.out_len as usize;
+        self.out[i / 64] |= ((color == Color::Red) as u64) << (i % 64);
+        self.out_len += 1;
+    }
+
+    /// The output sequence.
+    pub fn out_seq(&self) -> impl Iterator<Item = Color> + '_ {
+        (0..self.out_len as usize).map(move |i| {
+            if self.out[i / 64] >> (i % 64) & 1 == 1 {
+                Color::Red
+            } else {
+                Color::Blue
+            }
+        })
+    }
+
+    /// Like `State::register`.
+    pub fn register(&self, mem_range: Range<usize>) -> u64 {
+        assert!(mem_range.end <= self.n_mem as usize);
+        let len = mem_range.end.saturating_sub(mem_range.start);
+        match len {
+            0 => 0,
+            64 => self.mem,
+            _ => (self.mem >> mem_range.start) & ((1 << len) - 1),
+        }
+    }
+
+    pub fn bit(&self, address: Addr) -> bool {
+        self.mem >> address & 1 == 1
+    }
+
+    // __________________________________ convenience constructors
+
+    /// Must be `has_room_for` these balls.
+    pub fn with_balls(mut self, blue_red: [u8; 2]) -> Self {
+        assert!(self.has_room_for(blue_red), "output sequence too long");
+        self.balls = blue_red;
+        self
+    }
+
+    /// Is there room in the output sequence for all these balls, after the
+    /// balls output so far?
+    pub fn has_room_for(&self, blue_red: [u8; 2]) -> bool {
+        self.out_len as usize + blue_red[0] as usize + blue_red[1] as usize <= OUT_WORDS * 64
+    }
+
+    pub fn with_start(mut self, start_button: Color) -> Self {
+        self.start_button = start_button;
+        self
+    }
+
+    /// Like `State::with_register`.
+    pub fn with_register(mut self, mem_range: Range<usize>, number: u64) -> Self {
+        assert!(mem_range.end <= self.n_mem as usize);
+        for (i, addr) in mem_range.enumerate() {
+            self.mem &= !(1 << addr);
+            self.mem |= (number >> i & 1) << addr;
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const B: Addr = BLUE_LEVER;
+    const R: Addr = RED_LEVER;
+
+    #[test]
+    fn roundtrip() {
+        let p = State {
+            entry: [0, 1],
+            instr: vec![ijmp(0, 1, B), ijmp(0, R, INTERC0)],
+            mem: vec![false, true, true],
+            out_seq: vec![Color::Red, Color::Blue],
+            balls: [3, 2],
+            ..State::default()
+        };
+        let c = p.to_compact().unwrap();
+        assert_eq!(c.to_state().to_string(), p.to_string());
+        assert_eq!(c.to_state().out_seq, p.out_seq);
+        assert_eq!(c.register(0..3), 0b110);
+        assert_eq!(c.with_register(0..2, 1).register(0..3), 0b101);
+        assert!(State::new(65).to_compact().is_none());
+        // no room to output all the balls
+        let full = State {
+            out_seq: vec![Color::Red; 3],
+            ..State::default()
+        };
+        assert!(full.with_balls([255, 254]).to_compact().is_some());
+        assert!(full.with_balls([255, 255]).to_compact().is_none());
+        let c = full.to_compact().unwrap();
+        assert!(c.has_room_for([255, 254]) && !c.has_room_for([255, 255]));
+    }
+
+    /// Every program with 2 instructions gives the same outcome as `try_run`.
+    #[test]
+    fn same_as_try_run() {
+        testing::same_as_try_run(|p, setups| {
+            let run = |&(balls, start, mem): &testing::Setup| {
+                let p = p
+                    .with_balls(balls)
+                    .with_start(start)
+                    .with_register(0..2, mem);
+                let mut c = p.to_compact().unwrap();
+                c.try_run().map(|()| c.to_state())
+            };
+            setups.iter().map(run).collect()
+        });
+    }
+}
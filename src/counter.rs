@@ -1,10 +1,11 @@
-use std::cell::Cell;
 use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
 
 /// A performance counter, used only to show
 /// how many candidate programs were searched.
+/// Can be shared between threads.
 #[derive(Default)]
-pub struct Counter(Cell<u64>);
+pub struct Counter(AtomicU64);
 
 impl Counter {
     pub fn new() -> Self {
@@ -16,15 +17,15 @@ impl Counter {
     }
 
     pub fn add(&self, rhs: u64) {
-        self.0.set(self.0.get() + rhs)
+        self.0.fetch_add(rhs, Relaxed);
     }
 
     pub fn get(&self) -> u64 {
-        self.0.get()
+        self.0.load(Relaxed)
     }
 
     pub fn take(&self) -> u64 {
-        self.0.take()
+        self.0.swap(0, Relaxed)
     }
 }
 
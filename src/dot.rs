@@ -0,0 +1,162 @@
+use super::*;
+use std::fmt::Write;
+
+impl State {
+    /// The instruction graph in Graphviz DOT format, e.g. for `dot -Tpng`.
+    ///
+    /// One node per instruction (`i: ijmp mem`), edges for `jmp0` and `jmp1`
+    /// labelled 0 and 1, entry edges from the start of the colors that may be
+    /// released, and a node for each lever, interceptor, `FALL` or `?` that is
+    /// jumped to. Instructions that share a memory address (gear bits) are
+    /// grouped in a cluster.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        let n = self.instr.len();
+        let (released, _) = self.reachable();
+        let colors: Vec<Color> = [Color::Blue, Color::Red]
+            .iter()
+            .copied()
+            .filter(|&c| released[usize::from(c)])
+            .collect();
+
+        out.push_str("digraph {\n");
+        out.push_str("    node [shape=box, fontname=monospace];\n");
+        for &c in &colors {
+            let name = if c == Color::Blue { "blue" } else { "red" };
+            writeln!(
+                out,
+                "    start_{} [label=\"start\", shape=circle, style=filled, fillcolor={}, fontcolor=white];",
+                name, name
+            )
+            .unwrap();
+        }
+
+        let mut users: Vec<Vec<usize>> = vec![vec![]; self.mem.len()];
+        let mut unshared = vec![];
+        for (i, instr) in self.instr.iter().enumerate() {
+            match users.get_mut(instr.mem as usize) {
+                Some(users) => users.push(i),
+                None => unshared.push(i),
+            }
+        }
+        let node = |out: &mut String, indent: &str, i: usize| {
+            writeln!(
+                out,
+                "{}i{} [label=\"{}: ijmp {}\"];",
+                indent, i, i, self.instr[i].mem
+            )
+            .unwrap()
+        };
+        for (mem, users) in users.iter().enumerate() {
+            match users.len() {
+                0 => (),
+                1 => unshared.push(users[0]),
+                _ => {
+                    writeln!(out, "    subgraph cluster_mem{} {{", mem).unwrap();
+                    writeln!(out, "        label=\"mem {}\";", mem).unwrap();
+                    for &i in users {
+                        node(&mut out, "        ", i);
+                    }
+                    out.push_str("    }\n");
+                }
+            }
+        }
+        unshared.sort_unstable();
+        for &i in &unshared {
+            node(&mut out, "    ", i);
+        }
+
+        let mut sinks: Vec<Addr> = colors
+            .iter()
+            .map(|&c| self.entry[usize::from(c)])
+            .chain(self.instr.iter().flat_map(|i| [i.jmp0, i.jmp1]))
+            .filter(|&a| (BLUE_LEVER..=UNDECIDED).contains(&a))
+            .collect();
+        sinks.sort_unstable();
+        sinks.dedup();
+        for &a in &sinks {
+            let style = match a {
+                BLUE_LEVER => ", style=filled, fillcolor=blue, fontcolor=white",
+                RED_LEVER => ", style=filled, fillcolor=red, fontcolor=white",
+                INTERC0..=INTERC2 => ", style=filled, fillcolor=gray30, fontcolor=white",
+                _ => ", style=dashed",
+            };
+            writeln!(
+                out,
+                "    {} [label=\"{}\", shape=ellipse{}];",
+                dot_id(a),
+                DisplayAddr(a),
+                style
+            )
+            .unwrap();
+        }
+
+        for &c in &colors {
+            let name = if c == Color::Blue { "blue" } else { "red" };
+            let entry = self.entry[usize::from(c)];
+            if (entry as usize) < n || (BLUE_LEVER..=UNDECIDED).contains(&entry) {
+                writeln!(out, "    start_{} -> {};", name, dot_id(entry)).unwrap();
+            }
+        }
+        for (i, instr) in self.instr.iter().enumerate() {
+            for (label, jmp) in [(0, instr.jmp0), (1, instr.jmp1)] {
+                if (jmp as usize) < n || (BLUE_LEVER..=UNDECIDED).contains(&jmp) {
+                    writeln!(out, "    i{} -> {} [label={}];", i, dot_id(jmp), label).unwrap();
+                }
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Node name of an instruction or special address.
+fn dot_id(addr: Addr) -> String {
+    match addr {
+        UNDECIDED => "undecided".into(),
+        a if a < BLUE_LEVER => format!("i{}", a),
+        a => DisplayAddr(a).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const B: Addr = BLUE_LEVER;
+
+    #[test]
+    fn dot() {
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(0, INTERC0, 2), ijmp(2, B, UNDECIDED)],
+            mem: vec![false; 3],
+            ..State::default()
+        };
+        assert_eq!(
+            p.to_dot(),
+            r#"digraph {
+    node [shape=box, fontname=monospace];
+    start_blue [label="start", shape=circle, style=filled, fillcolor=blue, fontcolor=white];
+    subgraph cluster_mem0 {
+        label="mem 0";
+        i0 [label="0: ijmp 0"];
+        i1 [label="1: ijmp 0"];
+    }
+    i2 [label="2: ijmp 2"];
+    B [label="B", shape=ellipse, style=filled, fillcolor=blue, fontcolor=white];
+    INTERC0 [label="INTERC0", shape=ellipse, style=filled, fillcolor=gray30, fontcolor=white];
+    undecided [label="?", shape=ellipse, style=dashed];
+    start_blue -> i0;
+    i0 -> i1 [label=0];
+    i0 -> B [label=1];
+    i1 -> INTERC0 [label=0];
+    i1 -> i2 [label=1];
+    i2 -> B [label=0];
+    i2 -> undecided [label=1];
+}
+"#
+        );
+    }
+}
@@ -1,13 +1,37 @@
 mod addr;
+mod batch;
+mod board;
 mod color;
+mod compact;
 mod instr;
+mod machine;
+mod parse;
+mod puzzle;
+mod puzzle_file;
 mod state;
+mod svg;
+#[cfg(test)]
+mod testing;
+mod trace;
+mod validate;
 mod alia;
+mod canon;
 mod counter;
+mod dot;
 
 pub use addr::*;
+pub use batch::*;
+pub use board::*;
 pub use counter::*;
 pub use color::*;
+pub use compact::*;
 pub use instr::*;
+pub use machine::*;
+pub use parse::*;
+pub use puzzle::*;
+pub use puzzle_file::*;
 pub use state::*;
+pub use trace::*;
+pub use validate::*;
 pub use alia::*;
+pub use canon::*;
@@ -0,0 +1,324 @@
+use super::*;
+
+/// Runs a `State` step by step, e.g. for a debugger.
+///
+/// Unlike `State::run`, which runs until the machine halts, a `Machine` can
+/// execute a single instruction (`step_instr`), let a single ball go through
+/// (`step_ball`), or run until a condition or a breakpoint is reached
+/// (`run_until`), and the state can be inspected in between.
+/// Errors are reported like `State::try_run` does.
+#[derive(Clone, Debug)]
+pub struct Machine {
+    state: State,
+    /// address the current ball is at, `None` when halted.
+    pc: Option<Addr>,
+    /// color of the current ball.
+    cbr: Color,
+    /// last instruction executed, and the jump it took.
+    from: (Addr, bool),
+    breakpoints: Vec<Breakpoint>,
+}
+
+/// Where `Machine::run_until` stops.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Before executing the instruction at this address.
+    Instr(Addr),
+    /// After an instruction flipped the bit at this memory address.
+    Mem(Addr),
+}
+
+/// Why `Machine::run_until` stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// The machine halted: out of balls, or a ball was intercepted.
+    Halted,
+    Breakpoint(Breakpoint),
+    /// The predicate passed to `run_until` returned true.
+    Condition,
+}
+
+impl Machine {
+    /// A machine about to run `state`. The start button has been pressed:
+    /// the first ball is released, and is at its entrypoint.
+    pub fn new(mut state: State) -> Self {
+        let (pc, cbr) = match state.try_release(state.start_button) {
+            Some((pc, cbr)) => (Some(pc), cbr),
+            None => (None, state.start_button),
+        };
+        Self {
+            state,
+            pc,
+            cbr,
+            // like `State::exec`, for a ball that enters at `UNDECIDED`
+            from: (pc.unwrap_or(0), false),
+            breakpoints: vec![],
+        }
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Change the state, e.g. memory or balls, between steps.
+    pub fn state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+
+    pub fn into_state(self) -> State {
+        self.state
+    }
+
+    /// Address of the instruction to be executed next (or, before the first
+    /// step, the entrypoint of the first ball), `None` if the machine has halted.
+    pub fn pc(&self) -> Option<Addr> {
+        self.pc
+    }
+
+    /// Color of the current ball, or `None` if the machine has halted.
+    pub fn cbr(&self) -> Option<Color> {
+        self.pc.map(|_| self.cbr)
+    }
+
+    /// Index of the current ball (0: the ball released by the start button),
+    /// which is the number of balls output so far, as in `RunError`.
+    pub fn ball(&self) -> usize {
+        self.state.out_seq.len()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.pc.is_none()
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint)
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.retain(|&b| b != breakpoint)
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Execute one instruction, then let the ball fall to the next
+    /// instruction. If it reaches a lever instead, the ball is output and the
+    /// next ball is released, until a ball reaches an instruction again or
+    /// the machine halts. Does nothing if the machine has halted.
+    pub fn step_instr(&mut self) -> Result<(), RunError> {
+        let pc = match self.pc {
+            Some(pc) => pc,
+            None => return Ok(()),
+        };
+        self.fall()?;
+        if self.pc != Some(pc) {
+            // the ball did not reach an instruction yet: that was the step
+            return Ok(());
+        }
+
+        let ball = self.ball();
+        let Instr { mem, jmp0, jmp1 } = match self.state.instr.get(pc as usize) {
+            Some(&instr) => instr,
+            None => return self.fail(RunError::InstrOutOfRange { pc, ball }),
+        };
+        let bit = match self.state.mem.get_mut(mem as usize) {
+            Some(bit) => bit,
+            None => return self.fail(RunError::MemOutOfRange { pc, mem, ball }),
+        };
+        *bit = !*bit;
+        let target = if *bit { jmp1 } else { jmp0 };
+        self.from = (pc, *bit);
+        if target <= pc {
+            return self.fail(RunError::BackwardJump { pc, target, ball });
+        }
+        self.pc = Some(target);
+        self.fall()
+    }
+
+    /// Run until the current ball leaves the board and the next ball is
+    /// released, the machine halts, or a breakpoint is reached.
+    pub fn step_ball(&mut self) -> Result<Stop, RunError> {
+        let ball = self.ball();
+        self.run_until(|m| m.ball() != ball)
+    }
+
+    /// Run until the machine halts, or a breakpoint is reached.
+    pub fn run(&mut self) -> Result<Stop, RunError> {
+        self.run_until(|_| false)
+    }
+
+    /// Execute instructions until `stop` returns true (checked after each
+    /// `step_instr`), the machine halts, or a breakpoint is reached.
+    /// An instruction breakpoint at the current PC does not stop the first
+    /// step, so that calling `run_until` again continues from a breakpoint.
+    pub fn run_until<F>(&mut self, mut stop: F) -> Result<Stop, RunError>
+    where
+        F: FnMut(&Machine) -> bool,
+    {
+        let mut first = true;
+        loop {
+            let pc = match self.pc {
+                None => return Ok(Stop::Halted),
+                Some(pc) => pc,
+            };
+            if !first && self.breakpoints.contains(&Breakpoint::Instr(pc)) {
+                return Ok(Stop::Breakpoint(Breakpoint::Instr(pc)));
+            }
+            first = false;
+
+            let mem = self.state.instr.get(pc as usize).map(|i| i.mem);
+            self.step_instr()?;
+            if let Some(mem) = mem {
+                if self.breakpoints.contains(&Breakpoint::Mem(mem)) {
+                    return Ok(Stop::Breakpoint(Breakpoint::Mem(mem)));
+                }
+            }
+            if self.is_halted() {
+                return Ok(Stop::Halted);
+            }
+            if stop(self) {
+                return Ok(Stop::Condition);
+            }
+        }
+    }
+
+    /// Let the ball fall through special addresses (levers release the next
+    /// ball) until it reaches an instruction or the machine halts.
+    fn fall(&mut self) -> Result<(), RunError> {
+        while let Some(pc) = self.pc {
+            match pc {
+                pc if pc < BLUE_LEVER => return Ok(()),
+                addr @ (BLUE_LEVER | RED_LEVER) => {
+                    self.state.out_seq.push(self.cbr);
+                    let next_color = if addr == BLUE_LEVER {
+                        Color::Blue
+                    } else {
+                        Color::Red
+                    };
+                    self.pc = match self.state.try_release(next_color) {
+                        None => None,
+                        Some((pc, cbr)) => {
+                            self.cbr = cbr;
+                            Some(pc)
+                        }
+                    };
+                }
+                addr @ INTERC0..=INTERC2 => {
+                    self.state.intercept[(addr - INTERC0) as usize] = Some(self.cbr);
+                    self.pc = None;
+                }
+                UNDECIDED => {
+                    self.state.undecided = Some(self.from);
+                    self.pc = None;
+                }
+                invalid => {
+                    let ball = self.ball();
+                    return self.fail(RunError::FellOff { pc: invalid, ball });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Halt with an error.
+    fn fail(&mut self, err: RunError) -> Result<(), RunError> {
+        self.pc = None;
+        Err(err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use Color::*;
+
+    const B: Addr = BLUE_LEVER;
+
+    fn counter() -> State {
+        State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, 2, B), ijmp(2, B, B)],
+            mem: vec![false; 3],
+            balls: [5, 0],
+            ..State::default()
+        }
+    }
+
+    #[test]
+    fn step() {
+        let mut m = Machine::new(counter());
+        assert_eq!((m.pc(), m.cbr(), m.ball()), (Some(0), Some(Blue), 0));
+
+        m.step_instr().unwrap();
+        // first ball: sets bit 0 and is output, second ball is released
+        assert_eq!((m.pc(), m.ball()), (Some(0), 1));
+        assert_eq!(m.state().mem_str(), "100");
+        m.step_instr().unwrap();
+        assert_eq!((m.pc(), m.ball()), (Some(1), 1));
+
+        assert_eq!(m.step_ball(), Ok(Stop::Condition));
+        assert_eq!((m.pc(), m.ball()), (Some(0), 2));
+        assert_eq!(m.state().register(0..3), 2);
+
+        assert_eq!(m.run(), Ok(Stop::Halted));
+        assert_eq!(m.pc(), None);
+        assert_eq!(m.into_state().to_string(), counter().run().to_string());
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut m = Machine::new(counter());
+        m.add_breakpoint(Breakpoint::Instr(2));
+        assert_eq!(m.run(), Ok(Stop::Breakpoint(Breakpoint::Instr(2))));
+        // 4th ball carried through bits 0 and 1, about to set bit 2
+        assert_eq!(m.state().mem_str(), "000");
+        assert_eq!(m.ball(), 3);
+
+        m.remove_breakpoint(Breakpoint::Instr(2));
+        m.add_breakpoint(Breakpoint::Mem(0));
+        assert_eq!(m.run(), Ok(Stop::Breakpoint(Breakpoint::Mem(0))));
+        assert_eq!(m.state().register(0..3), 5);
+
+        assert_eq!(m.run_until(|m| m.ball() == 100), Ok(Stop::Halted));
+    }
+
+    #[test]
+    fn errors() {
+        let mut m = Machine::new(counter().with_entry([FALL, FALL]));
+        assert_eq!(m.step_instr(), Err(RunError::FellOff { pc: FALL, ball: 0 }));
+        assert!(m.is_halted());
+
+        let mut p = counter();
+        p.instr[1].jmp1 = 0;
+        let mut m = Machine::new(p);
+        assert_eq!(
+            m.run(),
+            Err(RunError::BackwardJump {
+                pc: 1,
+                target: 0,
+                ball: 1
+            })
+        );
+        assert!(m.is_halted());
+    }
+
+    /// Every program with 2 instructions ends like `try_run`.
+    #[test]
+    fn same_as_try_run() {
+        testing::same_as_try_run(|p, setups| {
+            let run = |&(balls, start, mem): &testing::Setup| {
+                let mut m = Machine::new(
+                    p.with_balls(balls)
+                        .with_start(start)
+                        .with_register(0..2, mem),
+                );
+                m.run().map(|_| m.into_state())
+            };
+            setups.iter().map(run).collect()
+        });
+    }
+}
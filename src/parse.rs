@@ -0,0 +1,401 @@
+use super::*;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Error encountered while parsing a program,
+/// with the line and column (both 1-based) where it occurred.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub col: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, col {}: {}", self.line, self.col, self.msg)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Error returned by `State::parse_checked`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProgramError {
+    Parse(ParseError),
+    /// The program parses, but `State::validate` found these errors.
+    Invalid(Vec<Diagnostic>),
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProgramError::Parse(err) => write!(f, "{}", err),
+            ProgramError::Invalid(diags) => {
+                let lines: Vec<String> = diags.iter().map(Diagnostic::to_string).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+        }
+    }
+}
+
+impl Error for ProgramError {}
+
+/// Parses the text format emitted by `State`'s `Display` impl. E.g.:
+///     start: b
+///     start_blue: 0
+///     start_red: FALL
+///     mem:
+///         0: 1
+///     instr:
+///         0: ijmp 0 B R   # comments are allowed
+///
+/// Ball counts, intercepted balls and output sequence are not part of
+/// the text format, they are left at their default values.
+///
+/// Parsing is purely syntactic: programs that would fail to run (e.g.
+/// backward jumps) are accepted, use `State::parse_checked` to reject them.
+impl FromStr for State {
+    type Err = ParseError;
+
+    fn from_str(src: &str) -> Result<Self, ParseError> {
+        Parser::new(src).parse_state()
+    }
+}
+
+impl State {
+    /// Parse a program (see `FromStr`), and reject it if `validate` reports
+    /// errors. Warnings are not reported, call `validate` to get them.
+    pub fn parse_checked(src: &str) -> Result<State, ProgramError> {
+        let p: State = src.parse().map_err(ProgramError::Parse)?;
+        let errors: Vec<Diagnostic> = p
+            .validate()
+            .into_iter()
+            .filter(Diagnostic::is_error)
+            .collect();
+        if errors.is_empty() {
+            Ok(p)
+        } else {
+            Err(ProgramError::Invalid(errors))
+        }
+    }
+}
+
+/// A whitespace-separated word, with its position in the source.
+#[derive(Clone, Copy)]
+pub(crate) struct Token<'a> {
+    pub line: usize,
+    pub col: usize,
+    pub text: &'a str,
+}
+
+impl<'a> Token<'a> {
+    pub fn error<T>(&self, msg: impl Into<String>) -> Result<T, ParseError> {
+        Err(ParseError {
+            line: self.line,
+            col: self.col,
+            msg: msg.into(),
+        })
+    }
+
+    /// Strip the trailing `:` of a key or label. E.g. `mem:` -> `mem`, `3:` -> `3`.
+    pub fn label(&self) -> Option<&'a str> {
+        self.text.strip_suffix(':')
+    }
+
+    pub fn addr(&self) -> Result<Addr, ParseError> {
+        match parse_addr(self.text) {
+            Some(addr) => Ok(addr),
+            None => self.error(format!("invalid address: `{}`", self.text)),
+        }
+    }
+
+    pub fn number<T: FromStr>(&self) -> Result<T, ParseError> {
+        match self.text.parse() {
+            Ok(v) => Ok(v),
+            Err(_) => self.error(format!("invalid number: `{}`", self.text)),
+        }
+    }
+
+    pub fn bit(&self) -> Result<bool, ParseError> {
+        match self.text {
+            "0" => Ok(false),
+            "1" => Ok(true),
+            _ => self.error(format!("expected 0 or 1, found `{}`", self.text)),
+        }
+    }
+
+    pub fn color(&self) -> Result<Color, ParseError> {
+        let mut chars = self.text.chars();
+        match (chars.next().and_then(Color::from_char), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => self.error(format!("expected b or r, found `{}`", self.text)),
+        }
+    }
+}
+
+/// Splits source text into lines of tokens,
+/// dropping `#` comments and blank lines.
+pub(crate) struct Parser<'a> {
+    lines: Vec<Vec<Token<'a>>>,
+    pos: usize,
+    /// line number just past the end of input, for "unexpected end" errors.
+    eof: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(src: &'a str) -> Self {
+        let mut lines = vec![];
+        let mut eof = 1;
+        for (i, line) in src.lines().enumerate() {
+            eof = i + 2;
+            let line = match line.find('#') {
+                Some(comment) => &line[..comment],
+                None => line,
+            };
+            let mut tokens = vec![];
+            let mut start = None;
+            for (col, (byte, c)) in line
+                .char_indices()
+                .chain(Some((line.len(), ' ')))
+                .enumerate()
+            {
+                match (start, c.is_whitespace()) {
+                    (None, false) => start = Some((col, byte)),
+                    (Some((start_col, start_byte)), true) => {
+                        tokens.push(Token {
+                            line: i + 1,
+                            col: start_col + 1,
+                            text: &line[start_byte..byte],
+                        });
+                        start = None;
+                    }
+                    _ => (),
+                }
+            }
+            if !tokens.is_empty() {
+                lines.push(tokens);
+            }
+        }
+        Self { lines, pos: 0, eof }
+    }
+
+    /// The next non-empty line, without consuming it.
+    pub fn peek(&self) -> Option<&[Token<'a>]> {
+        self.lines.get(self.pos).map(|l| &l[..])
+    }
+
+    /// Consume the next non-empty line.
+    pub fn next_line(&mut self) -> Result<Vec<Token<'a>>, ParseError> {
+        match self.lines.get(self.pos) {
+            Some(line) => {
+                self.pos += 1;
+                Ok(line.clone())
+            }
+            None => self.error_at_end("unexpected end of input"),
+        }
+    }
+
+    /// Error located just past the end of the input.
+    pub fn error_at_end<T>(&self, msg: impl Into<String>) -> Result<T, ParseError> {
+        Err(ParseError {
+            line: self.eof,
+            col: 1,
+            msg: msg.into(),
+        })
+    }
+
+    /// Does the next line start with `key:`?
+    pub fn at_key(&self, key: &str) -> bool {
+        matches!(self.peek(), Some(line) if line[0].label() == Some(key))
+    }
+
+    /// Consume a line of the form `key: arg1 arg2 ...`,
+    /// and check that it has exactly `n_args` arguments.
+    pub fn key(&mut self, key: &str, n_args: usize) -> Result<Vec<Token<'a>>, ParseError> {
+        let line = self.next_line()?;
+        if line[0].label() != Some(key) {
+            return line[0].error(format!("expected `{}:`, found `{}`", key, line[0].text));
+        }
+        Self::n_args(&line, n_args)?;
+        Ok(line[1..].to_vec())
+    }
+
+    /// Consume a line of the form `label: arg1 arg2 ...`,
+    /// where label must be the number `index`.
+    pub fn labelled(&mut self, index: usize, n_args: usize) -> Result<Vec<Token<'a>>, ParseError> {
+        let line = self.next_line()?;
+        match line[0].label().map(str::parse::<usize>) {
+            Some(Ok(label)) if label == index => (),
+            _ => {
+                return line[0].error(format!(
+                    "expected label `{}:`, found `{}`",
+                    index, line[0].text
+                ))
+            }
+        }
+        Self::n_args(&line, n_args)?;
+        Ok(line[1..].to_vec())
+    }
+
+    fn n_args(line: &[Token], n_args: usize) -> Result<(), ParseError> {
+        let got = line.len() - 1;
+        if got < n_args {
+            let last = line[got];
+            return Err(ParseError {
+                line: last.line,
+                col: last.col + last.text.chars().count(),
+                msg: format!(
+                    "expected {} argument(s) after `{}`, found {}",
+                    n_args, line[0].text, got
+                ),
+            });
+        }
+        if got > n_args {
+            return line[n_args + 1].error(format!("unexpected `{}`", line[n_args + 1].text));
+        }
+        Ok(())
+    }
+
+    pub fn expect_end(&self) -> Result<(), ParseError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(line) => line[0].error(format!("unexpected `{}`", line[0].text)),
+        }
+    }
+
+    pub fn parse_state(&mut self) -> Result<State, ParseError> {
+        let start_button = self.key("start", 1)?[0].color()?;
+        let entry_blue = self.key("start_blue", 1)?[0].addr()?;
+        let entry_red = self.key("start_red", 1)?[0].addr()?;
+        let mut s = State {
+            start_button,
+            entry: [entry_blue, entry_red],
+            ..State::default()
+        };
+
+        self.key("mem", 0)?;
+        while !self.at_key("instr") && self.peek().is_some() {
+            let args = self.labelled(s.mem.len(), 1)?;
+            s.mem.push(args[0].bit()?);
+        }
+
+        self.key("instr", 0)?;
+        while self.peek().is_some() {
+            let args = self.labelled(s.instr.len(), 4)?;
+            if args[0].text != "ijmp" {
+                return args[0].error(format!("expected `ijmp`, found `{}`", args[0].text));
+            }
+            s.instr
+                .push(ijmp(args[1].number()?, args[2].addr()?, args[3].addr()?));
+        }
+
+        self.expect_end()?;
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const COUNTER: &str = "
+# 4 bit counter, counts the blue balls.
+
+start: b          # start button releases a blue ball
+start_blue: 0     # blue ball enters at instruction 0
+start_red:  0     # never exercised in this program
+
+mem:
+	0: 0  # these 4 bits form the register
+	1: 0
+	2: 0
+	3: 0
+
+instr:
+	0: ijmp 0 1 B
+	1: ijmp 1 2 B
+	2: ijmp 2 3 B
+	3: ijmp 3 B B
+";
+
+    #[test]
+    fn parse_counter() {
+        let p: State = COUNTER.parse().unwrap();
+        assert_eq!(p.entry, [0, 0]);
+        assert_eq!(p.mem_str(), "0000");
+        assert_eq!(p.instr.len(), 4);
+        for n in 0..16 {
+            assert_eq!(p.with_balls([n, 0]).run().register(0..4), n as u64);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let p = State {
+            start_button: Color::Red,
+            entry: [FALL, 1],
+            mem: vec![true, false],
+            instr: vec![ijmp(0, 1, INTERC2), ijmp(0, RED_LEVER, BLUE_LEVER)],
+            ..State::default()
+        };
+        let text = p.to_string();
+        let q: State = text.parse().unwrap();
+        assert_eq!(q.to_string(), text);
+
+        // programs that do not validate parse too
+        let text = State::default().to_string();
+        assert_eq!(text.parse::<State>().unwrap().to_string(), text);
+        let backward = State {
+            entry: [0, FALL],
+            mem: vec![false],
+            instr: vec![ijmp(0, 0, BLUE_LEVER)],
+            ..State::default()
+        };
+        let q: State = backward.to_string().parse().unwrap();
+        assert!(q.validate().iter().any(Diagnostic::is_error));
+    }
+
+    #[test]
+    fn parse_checked() {
+        assert!(State::parse_checked(COUNTER).is_ok());
+        let backward = State {
+            entry: [0, FALL],
+            mem: vec![false],
+            instr: vec![ijmp(0, 0, BLUE_LEVER)],
+            ..State::default()
+        };
+        let err = State::parse_checked(&backward.to_string()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error: instruction 0 jmp0 jumps backwards to 0"
+        );
+        match State::parse_checked("start: x") {
+            Err(ProgramError::Parse(e)) => assert_eq!((e.line, e.col), (1, 8)),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        fn err(src: &str) -> (usize, usize) {
+            let e = src.parse::<State>().unwrap_err();
+            (e.line, e.col)
+        }
+        let head = "start: b\nstart_blue: 0\nstart_red: 0\n";
+        assert_eq!(err("start: x"), (1, 8));
+        assert_eq!(err("start: b\nstart_red: 0"), (2, 1));
+        assert_eq!(err(&format!("{}mem:\n\t1: 0\ninstr:\n", head)), (5, 2));
+        assert_eq!(
+            err(&format!("{}mem:\ninstr:\n 0: ijmp 0 1 Q\n", head)),
+            (6, 14)
+        );
+        assert_eq!(
+            err(&format!("{}mem:\ninstr:\n 0: ijmp 0 1\n", head)),
+            (6, 13)
+        );
+        assert_eq!(err(&format!("{}mem:\n", head)), (5, 1));
+    }
+}
@@ -0,0 +1,394 @@
+use super::*;
+use std::fmt;
+use std::ops::Range;
+
+/// Tells whether a program solves a puzzle.
+/// Implemented by `Puzzle`, and by any `Fn(&State) -> bool`.
+pub trait Test {
+    fn test(&self, p: &State) -> bool;
+}
+
+impl<F> Test for F
+where
+    F: Fn(&State) -> bool,
+{
+    fn test(&self, p: &State) -> bool {
+        self(p)
+    }
+}
+
+/// A puzzle specified as data: a list of test cases
+/// that a program must all pass.
+#[derive(Clone, Debug, Default)]
+pub struct Puzzle {
+    pub cases: Vec<Case>,
+}
+
+/// One run of the program: how the board is set up before the run,
+/// and what must be observed afterwards.
+#[derive(Clone, Debug, Default)]
+pub struct Case {
+    /// number of blue, red balls at the top of the board.
+    pub balls: [u8; 2],
+    pub start_button: Color,
+    /// memory ranges set to a number before the run (see `State::with_register`).
+    pub input: Vec<(Range<usize>, u64)>,
+
+    /// expected output sequence, if it matters.
+    pub out_seq: Option<Vec<Color>>,
+    /// memory ranges expected to hold a number after the run (see `State::register`).
+    pub registers: Vec<(Range<usize>, u64)>,
+    /// interceptor index, and the ball it is expected to hold
+    /// (`None`: interceptor must not be reached).
+    pub intercept: Vec<(usize, Option<Color>)>,
+}
+
+impl Puzzle {
+    pub fn new(cases: Vec<Case>) -> Self {
+        Self { cases }
+    }
+
+    /// Does program `p` pass all test cases?
+    pub fn check(&self, p: &State) -> bool {
+        // converted once, each case runs a copy
+        let compact = p.to_compact();
+        self.cases.iter().all(|c| match compact {
+            Some(compact) if compact.has_room_for(c.balls) => c.fits(p) && c.check_compact(compact),
+            _ => c.check(p),
+        })
+    }
+
+    /// Like `check`, but runs the cases together in a `Batch` (64 at a time).
+    /// Several times faster for programs that pass most cases, e.g. to verify
+    /// solutions, but slower in a search, where most programs already fail the
+    /// first case and `check` stops there.
+    pub fn check_batch(&self, p: &State) -> bool {
+        self.cases.iter().all(|c| c.fits(p))
+            && self.cases.chunks(LANES).all(|cases| {
+                let mut batch = Batch::new(p, cases.len());
+                for (lane, c) in cases.iter().enumerate() {
+                    c.setup_lane(&mut batch, lane);
+                }
+                let out = batch.run();
+                cases
+                    .iter()
+                    .enumerate()
+                    .all(|(lane, c)| c.check_lane(&out, lane))
+            })
+    }
+
+    /// Like `check`, but for a partially decided program in `search_lazy`.
+    /// Runs that halt on an undecided jump are only checked
+    /// for their output sequence so far.
+    pub fn check_partial(&self, t: &Trial) -> bool {
+        self.cases.iter().all(|c| {
+            if !c.fits(t.program()) {
+                return false;
+            }
+            let out = t.run(c.setup(t.program()));
+            match (&out.undecided, &c.out_seq) {
+                (None, _) => c.check_outcome(&out),
+                (Some(_), Some(want)) => want.starts_with(&out.out_seq),
+                (Some(_), None) => true,
+            }
+        })
+    }
+}
+
+impl Test for Puzzle {
+    fn test(&self, p: &State) -> bool {
+        self.check(p)
+    }
+}
+
+impl Test for &Puzzle {
+    fn test(&self, p: &State) -> bool {
+        self.check(p)
+    }
+}
+
+impl Case {
+    pub fn new(balls: [u8; 2]) -> Self {
+        Self {
+            balls,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_start(mut self, start_button: Color) -> Self {
+        self.start_button = start_button;
+        self
+    }
+
+    pub fn with_register(mut self, mem_range: Range<usize>, number: u64) -> Self {
+        self.input.push((mem_range, number));
+        self
+    }
+
+    pub fn with_bit(self, addr: Addr, value: bool) -> Self {
+        let addr = addr as usize;
+        self.with_register(addr..(addr + 1), value as u64)
+    }
+
+    pub fn expect_out(mut self, out_seq: &[Color]) -> Self {
+        self.out_seq = Some(out_seq.to_vec());
+        self
+    }
+
+    pub fn expect_register(mut self, mem_range: Range<usize>, number: u64) -> Self {
+        self.registers.push((mem_range, number));
+        self
+    }
+
+    pub fn expect_bit(self, addr: Addr, value: bool) -> Self {
+        let addr = addr as usize;
+        self.expect_register(addr..(addr + 1), value as u64)
+    }
+
+    pub fn expect_intercept(mut self, interceptor: usize, ball: Option<Color>) -> Self {
+        self.intercept.push((interceptor, ball));
+        self
+    }
+
+    /// Does program `p` pass this test case?
+    /// A program that cannot run (see `State::try_run`) does not.
+    pub fn check(&self, p: &State) -> bool {
+        self.fits(p)
+            && self
+                .setup(p)
+                .try_run()
+                .is_ok_and(|out| self.check_outcome(&out))
+    }
+
+    /// Like `check`, for a program that fits, as a `Compact` machine.
+    /// Does not allocate: `Puzzle::check` converts the program once, and
+    /// runs a copy of it for each case.
+    fn check_compact(&self, mut p: Compact) -> bool {
+        p = p.with_balls(self.balls).with_start(self.start_button);
+        for (range, number) in &self.input {
+            p = p.with_register(range.clone(), *number);
+        }
+        if p.try_run().is_err() {
+            return false;
+        }
+        if let Some(want) = &self.out_seq {
+            if !p.out_seq().eq(want.iter().copied()) {
+                return false;
+            }
+        }
+        self.registers
+            .iter()
+            .all(|(range, number)| p.register(range.clone()) == *number)
+            && self
+                .intercept
+                .iter()
+                .all(|&(i, ball)| p.intercept.get(i) == Some(&ball))
+    }
+
+    /// Program `p`, with balls and input memory set up for this case.
+    fn setup(&self, p: &State) -> State {
+        let mut p = p.with_balls(self.balls).with_start(self.start_button);
+        for (range, number) in &self.input {
+            p = p.with_register(range.clone(), *number);
+        }
+        p
+    }
+
+    /// Set up `lane` of `batch` for this case, like `setup`.
+    fn setup_lane(&self, batch: &mut Batch, lane: usize) {
+        batch.set_balls(lane, self.balls);
+        batch.set_start(lane, self.start_button);
+        for (range, number) in &self.input {
+            batch.set_register(lane, range.clone(), *number);
+        }
+    }
+
+    /// Like `check_outcome`, for `lane` of a batch set up by `setup_lane`.
+    fn check_lane(&self, out: &Batch, lane: usize) -> bool {
+        out.error(lane).is_none()
+            && self
+                .out_seq
+                .as_ref()
+                .is_none_or(|want| out.out_seq_eq(lane, want))
+            && self
+                .registers
+                .iter()
+                .all(|(range, number)| out.register(lane, range.clone()) == *number)
+            && self
+                .intercept
+                .iter()
+                .all(|&(i, ball)| out.intercept(lane).get(i) == Some(&ball))
+    }
+
+    /// Does program `p` have all the memory this case refers to?
+    fn fits(&self, p: &State) -> bool {
+        let n_mem = p.mem.len();
+        self.input
+            .iter()
+            .chain(&self.registers)
+            .all(|(range, _)| range.end <= n_mem)
+    }
+
+    fn check_outcome(&self, out: &State) -> bool {
+        if let Some(want) = &self.out_seq {
+            if &out.out_seq != want {
+                return false;
+            }
+        }
+        self.registers
+            .iter()
+            .all(|(range, number)| out.register(range.clone()) == *number)
+            && self
+                .intercept
+                .iter()
+                .all(|&(i, ball)| out.intercept.get(i) == Some(&ball))
+    }
+}
+
+impl fmt::Display for Puzzle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, case) in self.cases.iter().enumerate() {
+            writeln!(f, "case {}: {}", i, case)?;
+        }
+        Ok(())
+    }
+}
+
+/// E.g.: `8 blue, 8 red balls, start b, mem[0..4] = 5 => output bbr, mem[4] = 1`
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} blue, {} red balls, start {}",
+            self.balls[0], self.balls[1], self.start_button
+        )?;
+        for (range, number) in &self.input {
+            write!(f, ", ")?;
+            fmt_register(range, *number, f)?;
+        }
+        write!(f, " =>")?;
+        let mut sep = " ";
+        if let Some(out_seq) = &self.out_seq {
+            let out: String = out_seq.iter().map(Color::as_char).collect();
+            write!(f, "{}output {}", sep, out)?;
+            sep = ", ";
+        }
+        for (range, number) in &self.registers {
+            write!(f, "{}", sep)?;
+            fmt_register(range, *number, f)?;
+            sep = ", ";
+        }
+        for (i, ball) in &self.intercept {
+            match ball {
+                Some(ball) => write!(f, "{}INTERC{}: {}", sep, i, ball)?,
+                None => write!(f, "{}INTERC{}: empty", sep, i)?,
+            }
+            sep = ", ";
+        }
+        if sep == " " {
+            write!(f, " anything")?;
+        }
+        Ok(())
+    }
+}
+
+fn fmt_register(range: &Range<usize>, number: u64, f: &mut fmt::Formatter) -> fmt::Result {
+    if range.len() == 1 {
+        write!(f, "mem[{}] = {}", range.start, number)
+    } else {
+        write!(f, "mem[{}..{}] = {}", range.start, range.end, number)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use Color::*;
+
+    fn counter() -> Puzzle {
+        Puzzle::new(
+            (0..16)
+                .map(|n| Case::new([n, 0]).expect_register(0..4, n as u64))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn check_counter() {
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, 2, B), ijmp(2, 3, B), ijmp(3, B, B)],
+            mem: vec![false; 4],
+            ..State::default()
+        };
+        assert!(counter().check(&p));
+        assert!(!counter().check(&p.with_bit(3, true)));
+        assert!(!counter().check(&State::new(3)));
+        // falls off the board instead of panicking
+        assert!(!counter().check(&p.with_entry([FALL, FALL])));
+
+        // too many balls for a `Compact` machine, after some output
+        let many = Puzzle::new(vec![Case::new([255, 255]).expect_register(0..4, 255 % 16)]);
+        let mut p = p.with_balls([0, 0]);
+        p.out_seq = vec![Blue; 3];
+        assert!(many.check(&p));
+    }
+
+    #[test]
+    fn solve_counter() {
+        let puzzle = counter();
+        let sol = search_bits(&State::new(4), &[B], &Options::first(), &puzzle);
+        assert_eq!(sol.found.len(), 1);
+
+        let lazy = search_lazy(&State::new(4), &[B], &Options::first(), |t| {
+            puzzle.check_partial(t)
+        });
+        assert!(puzzle.check(&lazy.found[0]));
+    }
+
+    #[test]
+    fn check_batch() {
+        // every candidate of a search passes `check_batch` iff it passes `check`
+        let set_reset = Puzzle::new(vec![
+            Case::new([2, 1]).expect_out(&[Blue, Red]),
+            Case::new([2, 1])
+                .with_start(Red)
+                .with_bit(0, true)
+                .expect_bit(1, false),
+            Case::new([1, 1]).expect_intercept(0, None),
+        ]);
+        for puzzle in [counter(), set_reset] {
+            let passed = Counter::new();
+            let sol = search_gear(
+                &State::new(2),
+                &[B, R, INTERC0],
+                &Options::default(),
+                |p: &State| {
+                    assert_eq!(puzzle.check_batch(p), puzzle.check(p), "{}", p);
+                    passed.add(puzzle.check(p) as u64);
+                    false
+                },
+            );
+            assert!(sol.tried > 1000);
+            // no 2-bit program counts to 15, but some pass the second puzzle
+            assert_eq!(passed.get() > 0, puzzle.cases.len() == 3);
+        }
+    }
+
+    #[test]
+    fn display() {
+        let case = Case::new([8, 8])
+            .with_register(0..4, 5)
+            .expect_out(&[Blue, Blue, Red])
+            .expect_bit(4, true)
+            .expect_intercept(0, None);
+        assert_eq!(
+            case.to_string(),
+            "8 blue, 8 red balls, start b, mem[0..4] = 5 => output bbr, mem[4] = 1, INTERC0: empty"
+        );
+    }
+
+    const B: Addr = BLUE_LEVER;
+    const R: Addr = RED_LEVER;
+}
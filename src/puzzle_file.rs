@@ -0,0 +1,331 @@
+use super::*;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// A puzzle, together with the parts that may be used to solve it.
+/// This is what a puzzle file describes, e.g.:
+///     # output 1 blue, 2 red, 3 blue balls
+///     bits: 4               # number of instructions (parts)
+///     parts: bits           # `bits`, or `gear` to also allow gear bits
+///     gear_bits: 1          # memory-only gear bits, at mem[bits..], with `gear`
+///     addr: B R INTERC0     # special addresses that may be used
+///     balls: 8 8            # default blue, red balls for each case
+///     case:
+///         out: brrbbb       # expected output sequence
+/// Besides `out:`, a case may contain
+///     balls: 3 0            # overrides the default balls
+///     start: r              # start button, default b
+///     set: 0..4 5           # input: mem[0..4] holds 5 before the run
+///     set: 4 1              # input: mem[4] holds 1 before the run
+///     expect: 0..4 5        # mem[0..4] must hold 5 after the run
+///     INTERC0: b            # INTERC0 must hold a blue ball (`-`: no ball)
+#[derive(Clone, Debug, Default)]
+pub struct PuzzleFile {
+    /// Number of instructions.
+    pub bits: usize,
+    /// Whether gear bits may be used, or only regular bits.
+    pub gear: bool,
+    /// Number of memory-only gear bits (see `search_gear`), following the
+    /// memory of the instructions.
+    pub gear_bits: usize,
+    /// Special addresses that may be used, e.g. `[BLUE_LEVER, INTERC0]`.
+    pub ext_addr: Vec<Addr>,
+    pub puzzle: Puzzle,
+}
+
+impl PuzzleFile {
+    /// Search all solutions (or up to `opts.max_solutions`).
+    /// Uses `search_lazy` for bits-only puzzles, `search_gear_par` otherwise.
+    pub fn solve(&self, opts: &Options) -> Solutions {
+        let init = State::new(self.bits).with_mem(vec![false; self.bits + self.gear_bits]);
+        if self.gear {
+            search_gear_par(&init, &self.ext_addr, opts, &self.puzzle)
+        } else {
+            search_lazy(&init, &self.ext_addr, opts, |t| {
+                self.puzzle.check_partial(t)
+            })
+        }
+    }
+
+    /// Search the smallest solutions, using up to `bits` instructions
+    /// (see `search_smallest`). Memory-only gear bits are not used.
+    pub fn solve_smallest(&self, opts: &Options) -> Smallest {
+        search_smallest(&self.ext_addr, self.bits, self.gear, opts, &self.puzzle)
+    }
+}
+
+impl FromStr for PuzzleFile {
+    type Err = ParseError;
+
+    fn from_str(src: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser::new(src);
+        let mut file = PuzzleFile::default();
+        let mut balls = [0, 0];
+        // checked once all keys are known, e.g. `gear_bits:` before `bits:`
+        let (mut bits, mut gear_bits) = (None, None);
+        let mut ranges = vec![];
+
+        while let Some(line) = parser.peek() {
+            let key = line[0];
+            match key.label() {
+                Some("bits") => {
+                    let arg = parser.key("bits", 1)?[0];
+                    file.bits = arg.number()?;
+                    bits = Some(arg);
+                }
+                Some("parts") => {
+                    let arg = parser.key("parts", 1)?[0];
+                    file.gear = match arg.text {
+                        "bits" => false,
+                        "gear" => true,
+                        _ => {
+                            return arg
+                                .error(format!("expected bits or gear, found `{}`", arg.text))
+                        }
+                    }
+                }
+                Some("addr") => {
+                    file.ext_addr.clear();
+                    for arg in &parser.next_line()?[1..] {
+                        match arg.addr()? {
+                            addr @ BLUE_LEVER..=INTERC2 => file.ext_addr.push(addr),
+                            _ => {
+                                return arg.error(format!(
+                                    "expected B, R or INTERC0-2, found `{}`",
+                                    arg.text
+                                ))
+                            }
+                        }
+                    }
+                }
+                Some("gear_bits") => {
+                    let arg = parser.key("gear_bits", 1)?[0];
+                    file.gear_bits = arg.number()?;
+                    gear_bits = Some(arg);
+                }
+                Some("balls") => balls = parse_balls(&parser.key("balls", 2)?)?,
+                Some("case") => {
+                    parser.key("case", 0)?;
+                    let case = parse_case(&mut parser, balls, &mut ranges)?;
+                    file.puzzle.cases.push(case);
+                }
+                _ => return key.error(format!("unexpected `{}`", key.text)),
+            }
+        }
+
+        if file.puzzle.cases.is_empty() {
+            return parser.error_at_end("expected `case:`");
+        }
+        if file.gear_bits > 0 && !file.gear {
+            return parser.error_at_end("`gear_bits:` requires `parts: gear`");
+        }
+        // instruction and memory addresses must stay below the special addresses
+        match bits {
+            None => return parser.error_at_end("expected `bits:`"),
+            Some(arg) if file.bits == 0 || file.bits >= BLUE_LEVER as usize => {
+                return arg.error(format!(
+                    "expected 1 to {} bits, found {}",
+                    BLUE_LEVER - 1,
+                    file.bits
+                ))
+            }
+            Some(_) => (),
+        }
+        let n_mem = file.bits + file.gear_bits;
+        if let Some(arg) = gear_bits.filter(|_| n_mem > BLUE_LEVER as usize) {
+            return arg.error(format!(
+                "{} bits and {} gear bits do not fit in memory, at most {} in total",
+                file.bits, file.gear_bits, BLUE_LEVER
+            ));
+        }
+        for (arg, range) in ranges {
+            if range.end > n_mem {
+                return arg.error(format!(
+                    "memory range `{}` is past the end of memory ({} bits)",
+                    arg.text, n_mem
+                ));
+            }
+        }
+        Ok(file)
+    }
+}
+
+/// Parse the lines following `case:`, up to the next `case:`.
+/// The memory ranges of `set:` and `expect:` are added to `ranges`, to be
+/// checked against the size of memory.
+fn parse_case<'a>(
+    parser: &mut Parser<'a>,
+    balls: [u8; 2],
+    ranges: &mut Vec<(Token<'a>, Range<usize>)>,
+) -> Result<Case, ParseError> {
+    let mut case = Case::new(balls);
+    while let Some(line) = parser.peek() {
+        let key = line[0];
+        match key.label() {
+            Some("case") => break,
+            Some("balls") => case.balls = parse_balls(&parser.key("balls", 2)?)?,
+            Some("start") => case.start_button = parser.key("start", 1)?[0].color()?,
+            Some("set") => {
+                let args = parser.key("set", 2)?;
+                let range = parse_range(&args[0])?;
+                ranges.push((args[0], range.clone()));
+                case.input.push((range, args[1].number()?));
+            }
+            Some("expect") => {
+                let args = parser.key("expect", 2)?;
+                let range = parse_range(&args[0])?;
+                ranges.push((args[0], range.clone()));
+                case.registers.push((range, args[1].number()?));
+            }
+            Some("out") => {
+                let args = parser.next_line()?;
+                let mut out_seq = vec![];
+                for arg in &args[1..] {
+                    for c in arg.text.chars() {
+                        match Color::from_char(c) {
+                            Some(c) => out_seq.push(c),
+                            None => return arg.error(format!("expected b or r, found `{}`", c)),
+                        }
+                    }
+                }
+                case.out_seq = Some(out_seq);
+            }
+            Some(label) if label.starts_with("INTERC") => {
+                let i = match parse_addr(label) {
+                    Some(addr @ INTERC0..=INTERC2) => (addr - INTERC0) as usize,
+                    _ => return key.error(format!("unexpected `{}`", key.text)),
+                };
+                let arg = parser.key(label, 1)?[0];
+                let ball = match arg.text {
+                    "-" => None,
+                    _ => Some(arg.color()?),
+                };
+                case.intercept.push((i, ball));
+            }
+            _ => return key.error(format!("unexpected `{}`", key.text)),
+        }
+    }
+    Ok(case)
+}
+
+fn parse_balls(args: &[Token]) -> Result<[u8; 2], ParseError> {
+    Ok([args[0].number()?, args[1].number()?])
+}
+
+/// Parse a memory range like `0..4`, or a single address like `3`.
+/// Registers are read as `u64`, so a range holds at most 63 bits.
+fn parse_range(t: &Token) -> Result<Range<usize>, ParseError> {
+    let range = match t.text.split_once("..") {
+        Some((start, end)) => start.parse().and_then(|s| end.parse().map(|e| s..e)),
+        None => t.text.parse().map(|a| a..(a + 1)),
+    };
+    match range {
+        Ok(range) if range.start < range.end && range.end - range.start < 64 => Ok(range),
+        Ok(range) if range.start < range.end => t.error(format!(
+            "memory range `{}` is too wide, at most 63 bits",
+            t.text
+        )),
+        _ => t.error(format!("invalid memory range: `{}`", t.text)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn parse_puzzle_file() {
+        let src = "
+            bits: 4
+            parts: gear
+            addr: B INTERC0
+            balls: 8 8
+            case:
+                out: bb rr
+                INTERC0: r
+            case:
+                balls: 3 0
+                start: r
+                set: 0..4 5
+                expect: 3 1
+                INTERC1: -
+        ";
+        let file: PuzzleFile = src.parse().unwrap();
+        assert_eq!(file.bits, 4);
+        assert!(file.gear);
+        assert_eq!(file.ext_addr, vec![BLUE_LEVER, INTERC0]);
+        assert_eq!(
+            file.puzzle.to_string(),
+            "case 0: 8 blue, 8 red balls, start b => output bbrr, INTERC0: r\n\
+             case 1: 3 blue, 0 red balls, start r, mem[0..4] = 5 => mem[3] = 1, INTERC1: empty\n"
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |src: &str| {
+            let e = src.parse::<PuzzleFile>().unwrap_err();
+            (e.line, e.col)
+        };
+        assert_eq!(err("bits: 4\nparts: wood"), (2, 8));
+        assert_eq!(err("addr: B 3"), (1, 9));
+        assert_eq!(err("bits: 4\ncase:\n  set: 4..2 1"), (3, 8));
+        assert_eq!(err("bits: 4\ncase:\n  INTERC3: b"), (3, 3));
+        assert_eq!(err("bits: 4\n"), (2, 1));
+        assert_eq!(err("bits: 4\ngear_bits: 1\ncase:\n  out: b\n"), (5, 1));
+        // sizes and ranges
+        assert_eq!(err("case:\n  out: b\n"), (3, 1));
+        assert_eq!(err("bits: 0\ncase:\n  out: b\n"), (1, 7));
+        assert_eq!(err("bits: 128\ncase:\n  out: b\n"), (1, 7));
+        assert_eq!(
+            err("bits: 100\nparts: gear\ngear_bits: 29\ncase:\n  out: b\n"),
+            (3, 12)
+        );
+        assert_eq!(err("bits: 4\ncase:\n  set: 2..5 1\n"), (3, 8));
+        assert_eq!(err("bits: 4\ncase:\n  expect: 4 1"), (3, 11));
+        assert_eq!(err("bits: 100\ncase:\n  set: 0..64 1"), (3, 8));
+        assert!("bits: 100\ncase:\n  set: 0..63 1"
+            .parse::<PuzzleFile>()
+            .is_ok());
+    }
+
+    #[test]
+    fn memory_only_gear_bits() {
+        let src = "
+            bits: 1
+            parts: gear
+            gear_bits: 1
+            addr: B
+            case:
+                balls: 1 0
+                expect: 1 1
+        ";
+        let file: PuzzleFile = src.parse().unwrap();
+        assert_eq!(file.gear_bits, 1);
+        let sol = file.solve(&Options::default());
+        assert!(!sol.found.is_empty());
+        for p in &sol.found {
+            assert_eq!(p.mem.len(), 2);
+            assert_eq!(p.instr[0].mem, 1);
+        }
+    }
+
+    #[test]
+    fn solve_examples() {
+        for src in [
+            include_str!("../puzzles/counter.txt"),
+            include_str!("../puzzles/sequence1.txt"),
+            include_str!("../puzzles/set_reset.txt"),
+        ] {
+            let file: PuzzleFile = src.parse().unwrap();
+            let sol = file.solve(&Options::first());
+            assert_eq!(sol.found.len(), 1);
+            assert!(file.puzzle.check(&sol.found[0]));
+
+            let smallest = file.solve_smallest(&Options::first());
+            assert!(smallest.n_instr <= file.bits);
+            assert!(file.puzzle.check(&smallest.solutions.found[0]));
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use super::*;
 use core::fmt;
+use std::error::Error;
 use std::ops::Range;
 
 /// A complete board state: input balls, the program, output sequence, ...
@@ -17,6 +18,10 @@ pub struct State {
 
     pub intercept: [Option<Color>; 3],
 
+    /// Set if the machine halted on an `UNDECIDED` jump:
+    /// the instruction address, and which jump was taken (false: jmp0, true: jmp1).
+    pub undecided: Option<(Addr, bool)>,
+
     /// sequence of balls output at the bottom of the board.
     pub out_seq: Vec<Color>,
 }
@@ -38,7 +43,29 @@ impl State {
         self
     }
 
+    /// Like `run`, but returns an error instead of panicking when the program
+    /// is malformed: when a ball falls off the board, jumps to an instruction
+    /// or uses memory that does not exist, or jumps backwards (up the board).
+    pub fn try_run(mut self) -> Result<Self, RunError> {
+        self.exec(0, true, &mut ())?;
+        Ok(self)
+    }
+
     fn run_mut(&mut self, verbosity: u8) {
+        if let Err(err) = self.exec(verbosity, false, &mut ()) {
+            panic!("{}", err)
+        }
+    }
+
+    /// Run the machine. If `strict`, backward jumps are errors too.
+    /// Events are recorded in `trace` (`&mut ()` to record nothing, which
+    /// compiles to the same code as no tracing at all).
+    pub(crate) fn exec<T: Tracer>(
+        &mut self,
+        verbosity: u8,
+        strict: bool,
+        trace: &mut T,
+    ) -> Result<(), RunError> {
         let v1 = verbosity >= 1;
         let v2 = verbosity >= 2;
 
@@ -48,16 +75,31 @@ impl State {
 
         // current program counter and falling ball color
         let (mut pc, mut cbr) = match self.try_release(self.start_button) {
-            None => return, // no balls to start with, immediately halt
+            None => return Ok(()), // no balls to start with, immediately halt
             Some(pc_cbr) => pc_cbr,
         };
+        trace.push(TraceEvent::Released {
+            ball: self.out_seq.len(),
+            color: cbr,
+            entry: pc,
+        });
+
+        // last instruction executed, and the jump it took
+        let mut from = (pc, false);
 
         loop {
             // tumble down:
             // execute invert-and-branch instructions until we jump to a special address
             while pc < BLUE_LEVER {
-                let Instr { mem, jmp0, jmp1 } = self.instr[pc as usize];
+                let ball = self.out_seq.len();
+                let Instr { mem, jmp0, jmp1 } = match self.instr.get(pc as usize) {
+                    Some(&instr) => instr,
+                    None => return Err(RunError::InstrOutOfRange { pc, ball }),
+                };
                 let dst = mem as usize;
+                if dst >= self.mem.len() {
+                    return Err(RunError::MemOutOfRange { pc, mem, ball });
+                }
 
                 if v2 {
                     print!(
@@ -69,10 +111,23 @@ impl State {
                 self.mem[dst] = !self.mem[dst];
 
                 // ...and branch
-                pc = if self.mem[dst] { jmp1 } else { jmp0 };
+                from = (pc, self.mem[dst]);
+                let target = if self.mem[dst] { jmp1 } else { jmp0 };
                 if v2 {
-                    println!("  jmp {}", pc);
+                    println!("  jmp {}", target);
                 }
+                trace.push(TraceEvent::Executed {
+                    ball,
+                    pc,
+                    mem,
+                    old: !self.mem[dst],
+                    new: self.mem[dst],
+                    jmp: target,
+                });
+                if strict && target <= pc {
+                    return Err(RunError::BackwardJump { pc, target, ball });
+                }
+                pc = target;
             }
 
             // bottom out:
@@ -80,6 +135,11 @@ impl State {
             match pc {
                 addr @ (BLUE_LEVER | RED_LEVER) => {
                     // current falling ball goes to output sequence
+                    trace.push(TraceEvent::Lever {
+                        ball: self.out_seq.len(),
+                        color: cbr,
+                        lever: addr,
+                    });
                     self.out_seq.push(cbr);
                     if v1 {
                         self.print_state();
@@ -93,30 +153,45 @@ impl State {
                     // try release next ball, if not exhausted.
                     // sets current ball register and program counter.
                     match self.try_release(next_color) {
-                        None => return, // out of balls, halt
+                        None => return Ok(()), // out of balls, halt
                         Some((new_pc, new_cbr)) => {
                             pc = new_pc;
                             cbr = new_cbr;
+                            trace.push(TraceEvent::Released {
+                                ball: self.out_seq.len(),
+                                color: cbr,
+                                entry: pc,
+                            });
                         }
                     };
                 }
                 addr @ INTERC0..=INTERC2 => {
                     // jumped to interceptor: store color and halt
                     self.intercept[(addr - INTERC0) as usize] = Some(cbr);
+                    trace.push(TraceEvent::Intercepted {
+                        ball: self.out_seq.len(),
+                        color: cbr,
+                        interceptor: addr,
+                    });
                     if v1 {
                         self.print_state();
                     }
-                    return;
+                    return Ok(());
+                }
+                UNDECIDED => {
+                    // reached a part of the program that is still to be decided
+                    self.undecided = Some(from);
+                    return Ok(());
+                }
+                invalid => {
+                    let ball = self.out_seq.len();
+                    return Err(RunError::FellOff { pc: invalid, ball });
                 }
-                invalid => panic!(
-                    "The ball fell off the board! (jumped to invalid PC: {})",
-                    invalid
-                ),
             }
         }
     }
 
-    fn try_release(&mut self, color: Color) -> Option<(Addr, Color)> {
+    pub(crate) fn try_release(&mut self, color: Color) -> Option<(Addr, Color)> {
         match self.balls[color as usize] {
             0 => None, // out of balls, halting.
             n => {
@@ -220,6 +295,51 @@ impl State {
     }
 }
 
+/// Error encountered by `State::try_run`. `pc` is the program counter where
+/// it happened, `ball` the index of the ball that was falling (0: the ball
+/// released by the start button).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RunError {
+    /// Jumped to `FALL`, or to an address that is neither an instruction
+    /// nor a special address.
+    FellOff { pc: Addr, ball: usize },
+    /// Jumped to an instruction address past the end of the program.
+    InstrOutOfRange { pc: Addr, ball: usize },
+    /// The instruction at `pc` uses memory address `mem`, past the end of memory.
+    MemOutOfRange { pc: Addr, mem: Addr, ball: usize },
+    /// The instruction at `pc` jumps to `target`, which is not below it.
+    BackwardJump { pc: Addr, target: Addr, ball: usize },
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RunError::FellOff { pc, ball } => write!(
+                f,
+                "The ball fell off the board! (jumped to invalid PC: {}, ball {})",
+                pc, ball
+            ),
+            RunError::InstrOutOfRange { pc, ball } => write!(
+                f,
+                "jumped to instruction {}, past the end of the program (ball {})",
+                pc, ball
+            ),
+            RunError::MemOutOfRange { pc, mem, ball } => write!(
+                f,
+                "instruction {} uses mem[{}], past the end of memory (ball {})",
+                pc, mem, ball
+            ),
+            RunError::BackwardJump { pc, target, ball } => write!(
+                f,
+                "instruction {} jumps backwards to {} (ball {})",
+                pc, target, ball
+            ),
+        }
+    }
+}
+
+impl Error for RunError {}
+
 impl fmt::Display for State {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "start: {}", self.start_button)?;
@@ -243,3 +363,49 @@ impl fmt::Display for State {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn try_run() {
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, BLUE_LEVER), ijmp(1, BLUE_LEVER, BLUE_LEVER)],
+            mem: vec![false; 2],
+            balls: [3, 1],
+            ..State::default()
+        };
+        assert_eq!(p.clone().try_run().unwrap().register(0..2), 3);
+
+        let err = |p: &State| p.clone().try_run().unwrap_err();
+        assert_eq!(
+            err(&p.with_start(Color::Red)),
+            RunError::FellOff { pc: FALL, ball: 0 }
+        );
+        let mut q = p.clone();
+        q.instr[1].jmp1 = 2;
+        // the second ball clears bit 0, sets bit 1 and jumps to instruction 2
+        assert_eq!(err(&q), RunError::InstrOutOfRange { pc: 2, ball: 1 });
+        q.instr[1].jmp1 = 0;
+        assert_eq!(
+            err(&q),
+            RunError::BackwardJump {
+                pc: 1,
+                target: 0,
+                ball: 1
+            }
+        );
+        q.instr[1].mem = 2;
+        assert_eq!(
+            err(&q),
+            RunError::MemOutOfRange {
+                pc: 1,
+                mem: 2,
+                ball: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use super::*;
+use std::fmt::Write;
+
+/// Fill colors for instructions that share a memory address (gear bits).
+const GEAR_COLORS: [&str; 6] = [
+    "#fde68a", "#bbf7d0", "#bfdbfe", "#fbcfe8", "#ddd6fe", "#fed7aa",
+];
+
+const BLUE: &str = "#2563eb";
+const RED: &str = "#dc2626";
+
+impl State {
+    /// The control-flow graph of the program as an SVG image: one node per
+    /// instruction (`i: ijmp mem`), with the entrypoints at the top, levers,
+    /// interceptors and `FALL` at the bottom, and jumps labelled 0 or 1.
+    /// Instructions that share a memory address (gear bits) have the same
+    /// fill color, and are linked by a dashed line.
+    pub fn to_svg(&self) -> String {
+        const W: i32 = 120; // node width
+        const H: i32 = 30; // node height
+        const DX: i32 = 150; // horizontal node spacing
+        const DY: i32 = 80; // vertical node spacing
+        const MARGIN: i32 = 20;
+
+        let n = self.instr.len();
+        let (released, _) = self.reachable();
+        let is_instr = |addr: Addr| (addr as usize) < n;
+        let is_sink = |addr: Addr| (BLUE_LEVER..=UNDECIDED).contains(&addr);
+
+        // nodes, by layer: instructions go below all instructions jumping to them
+        let mut layer = vec![1; n];
+        for i in 0..n {
+            for jmp in [self.instr[i].jmp0, self.instr[i].jmp1] {
+                if is_instr(jmp) && jmp as usize > i {
+                    layer[jmp as usize] = layer[jmp as usize].max(layer[i] + 1);
+                }
+            }
+        }
+        let colors: Vec<Color> = [Color::Blue, Color::Red]
+            .iter()
+            .copied()
+            .filter(|&c| released[usize::from(c)])
+            .collect();
+        let mut sinks: Vec<Addr> = colors
+            .iter()
+            .map(|&c| self.entry[usize::from(c)])
+            .chain(self.instr.iter().flat_map(|i| [i.jmp0, i.jmp1]))
+            .filter(|&a| is_sink(a))
+            .collect();
+        sinks.sort_unstable();
+        sinks.dedup();
+        let n_layers = layer.iter().max().map_or(1, |l| l + 1);
+        let mut layers: Vec<Vec<Node>> = vec![vec![]; n_layers + 1];
+        layers[0] = colors.iter().map(|&c| Node::Start(c)).collect();
+        for i in 0..n {
+            layers[layer[i]].push(Node::Instr(i as Addr));
+        }
+        layers[n_layers] = sinks.iter().map(|&a| Node::Sink(a)).collect();
+
+        // positions: node centers
+        let width = layers.iter().map(Vec::len).max().unwrap_or(1).max(1) as i32 * DX;
+        let mut pos = std::collections::HashMap::new();
+        for (l, nodes) in layers.iter().enumerate() {
+            let x0 = MARGIN + (width - nodes.len() as i32 * DX) / 2 + DX / 2;
+            for (k, &node) in nodes.iter().enumerate() {
+                pos.insert(node, (x0 + k as i32 * DX, MARGIN + H / 2 + l as i32 * DY));
+            }
+        }
+        let node_of = |addr: Addr| match addr {
+            a if is_instr(a) => Some(Node::Instr(a)),
+            a if is_sink(a) => Some(Node::Sink(a)),
+            _ => None,
+        };
+
+        let mut svg = Svg::new(
+            width + 2 * MARGIN,
+            MARGIN * 2 + H + (layers.len() as i32 - 1) * DY,
+        );
+
+        // edges first, so that nodes are drawn on top of them
+        let mut edges: Vec<(Node, Node, String)> = vec![];
+        for &c in &colors {
+            if let Some(to) = node_of(self.entry[usize::from(c)]) {
+                edges.push((Node::Start(c), to, String::new()));
+            }
+        }
+        for (i, instr) in self.instr.iter().enumerate() {
+            let from = Node::Instr(i as Addr);
+            match (node_of(instr.jmp0), node_of(instr.jmp1)) {
+                (Some(to0), Some(to1)) if to0 == to1 => edges.push((from, to0, "0,1".into())),
+                (to0, to1) => {
+                    edges.extend(to0.map(|to| (from, to, "0".into())));
+                    edges.extend(to1.map(|to| (from, to, "1".into())));
+                }
+            }
+        }
+        for (from, to, label) in &edges {
+            let (x0, y0) = pos[from];
+            let (x1, y1) = pos[to];
+            let (y0, y1) = (y0 + H / 2, y1 - H / 2);
+            svg.arrow(x0, y0, x1, y1);
+            if !label.is_empty() {
+                // near the source, on the side the edge goes to
+                let lx = x0 + (x1 - x0) / 4 + if x1 < x0 { -10 } else { 6 };
+                svg.text(lx, y0 + (y1 - y0) / 4, label, "#555");
+            }
+        }
+
+        // gear bits: link the instructions sharing each memory address
+        let mut shared: Vec<Vec<usize>> = vec![vec![]; self.mem.len()];
+        for (i, instr) in self.instr.iter().enumerate() {
+            if let Some(users) = shared.get_mut(instr.mem as usize) {
+                users.push(i)
+            }
+        }
+        shared.retain(|users| users.len() > 1);
+        let mut fill = vec!["#ffffff"; n];
+        for (k, users) in shared.iter().enumerate() {
+            let color = GEAR_COLORS[k % GEAR_COLORS.len()];
+            for w in users.windows(2) {
+                let (x0, y0) = pos[&Node::Instr(w[0] as Addr)];
+                let (x1, y1) = pos[&Node::Instr(w[1] as Addr)];
+                svg.dashed(x0, y0, x1, y1);
+            }
+            for &i in users {
+                fill[i] = color;
+            }
+        }
+
+        for &node in layers.iter().flatten() {
+            let (x, y) = pos[&node];
+            match node {
+                Node::Start(c) => {
+                    let (color, name) = match c {
+                        Color::Blue => (BLUE, "blue"),
+                        Color::Red => (RED, "red"),
+                    };
+                    svg.ellipse(x, y, W / 2, H / 2, color);
+                    svg.text(x, y + 4, &format!("start {}", name), "#fff");
+                }
+                Node::Instr(i) => {
+                    svg.rect(x - W / 2, y - H / 2, W, H, fill[i as usize]);
+                    let label = format!("{}: ijmp {}", i, self.instr[i as usize].mem);
+                    svg.text(x, y + 4, &label, "#000");
+                }
+                Node::Sink(a) => {
+                    let color = match a {
+                        BLUE_LEVER => BLUE,
+                        RED_LEVER => RED,
+                        INTERC0..=INTERC2 => "#374151",
+                        _ => "#9ca3af",
+                    };
+                    svg.ellipse(x, y, W / 2, H / 2, color);
+                    svg.text(x, y + 4, &DisplayAddr(a).to_string(), "#fff");
+                }
+            }
+        }
+        svg.finish()
+    }
+}
+
+impl Board {
+    /// The board as an SVG image: pegs, parts, where the balls enter (top)
+    /// and the levers (bottom).
+    pub fn to_svg(&self) -> String {
+        const S: i32 = 30; // cell size
+        const MARGIN: i32 = 20;
+
+        let (rows, cols) = (self.rows() as i32, self.cols() as i32);
+        let mut svg = Svg::new(cols * S + 2 * MARGIN, (rows + 2) * S + 2 * MARGIN);
+        let center =
+            |row: i32, col: i32| (MARGIN + col * S + S / 2, MARGIN + (row + 1) * S + S / 2);
+
+        // levers below the bottom row: blue in the left half, red in the right half
+        let (_, y) = center(rows, 0);
+        let half = (cols / 2 + 1) * S;
+        svg.rect(MARGIN, y - S / 4, half - 4, S / 2, BLUE);
+        svg.rect(MARGIN + half, y - S / 4, cols * S - half, S / 2, RED);
+
+        // entrypoints above the top row
+        for (color, fill) in [(Color::Blue, BLUE), (Color::Red, RED)] {
+            let (_, col, dir) = self.entry(color);
+            let (x, y) = center(-1, col as i32);
+            let dx = if dir == Dir::Right { S / 2 } else { -S / 2 };
+            svg.arrow(x - dx, y - S / 4, x - dx / 4, y + S / 4);
+            svg.circle(x - dx, y - S / 4, S / 5, fill, fill);
+        }
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let (x, y) = center(row, col);
+                let r = S / 3;
+                let part = self.get(row as usize, col as usize);
+                if part.is_some_and(|p| p.is_geared()) {
+                    svg.circle(x, y, r + 3, "#6b7280", "#e5e7eb");
+                }
+                match part {
+                    None => svg.circle(x, y, 2, "#9ca3af", "#9ca3af"),
+                    Some(Part::Ramp(dir)) => {
+                        let d = if dir == Dir::Right { r } else { -r };
+                        svg.line(x - d, y - r, x + d, y + r, "#16a34a", 4);
+                    }
+                    Some(Part::Crossover) => {
+                        svg.line(x - r, y - r, x + r, y + r, "#ea580c", 4);
+                        svg.line(x + r, y - r, x - r, y + r, "#ea580c", 4);
+                    }
+                    Some(Part::Bit(right)) | Some(Part::GearBit(right)) => {
+                        svg.circle(x, y, r, "#1e40af", "#ffffff");
+                        let d = if right { r } else { -r };
+                        svg.line(x, y, x + d, y + r, "#1e40af", 4);
+                    }
+                    Some(Part::Gear) => (),
+                    Some(Part::Interceptor) => {
+                        svg.rect(x - r, y - r, 2 * r, 2 * r, "#374151");
+                    }
+                }
+            }
+        }
+        svg.finish()
+    }
+}
+
+/// A node of the control-flow graph.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum Node {
+    Start(Color),
+    Instr(Addr),
+    /// A lever, an interceptor, `FALL` or `UNDECIDED`.
+    Sink(Addr),
+}
+
+/// Builds an SVG document.
+struct Svg {
+    out: String,
+}
+
+impl Svg {
+    fn new(width: i32, height: i32) -> Self {
+        let mut out = String::new();
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="monospace" font-size="12" text-anchor="middle">"#,
+            w = width,
+            h = height
+        )
+        .unwrap();
+        out.push_str(
+            r#"<defs><marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="6" markerHeight="6" orient="auto"><path d="M 0 0 L 10 5 L 0 10 z"/></marker></defs>"#,
+        );
+        out.push('\n');
+        Self { out }
+    }
+
+    fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, stroke: &str, width: i32) {
+        writeln!(
+            self.out,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}" stroke-linecap="round"/>"#,
+            x0, y0, x1, y1, stroke, width
+        )
+        .unwrap();
+    }
+
+    fn arrow(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        writeln!(
+            self.out,
+            r##"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="#000" marker-end="url(#arrow)"/>"##,
+            x0, y0, x1, y1
+        )
+        .unwrap();
+    }
+
+    fn dashed(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        writeln!(
+            self.out,
+            r##"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="#6b7280" stroke-width="2" stroke-dasharray="6 4"/>"##,
+            x0, y0, x1, y1
+        )
+        .unwrap();
+    }
+
+    fn rect(&mut self, x: i32, y: i32, w: i32, h: i32, fill: &str) {
+        writeln!(
+            self.out,
+            r##"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="#000"/>"##,
+            x, y, w, h, fill
+        )
+        .unwrap();
+    }
+
+    fn circle(&mut self, x: i32, y: i32, r: i32, stroke: &str, fill: &str) {
+        writeln!(
+            self.out,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="{}" stroke="{}" stroke-width="2"/>"#,
+            x, y, r, fill, stroke
+        )
+        .unwrap();
+    }
+
+    fn ellipse(&mut self, x: i32, y: i32, rx: i32, ry: i32, fill: &str) {
+        writeln!(
+            self.out,
+            r##"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" fill="{}" stroke="#000"/>"##,
+            x, y, rx, ry, fill
+        )
+        .unwrap();
+    }
+
+    fn text(&mut self, x: i32, y: i32, text: &str, fill: &str) {
+        writeln!(
+            self.out,
+            r#"<text x="{}" y="{}" fill="{}">{}</text>"#,
+            x, y, fill, text
+        )
+        .unwrap();
+    }
+
+    fn finish(mut self) -> String {
+        self.out.push_str("</svg>\n");
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const B: Addr = BLUE_LEVER;
+    const R: Addr = RED_LEVER;
+
+    #[test]
+    fn graph() {
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(0, R, INTERC0), ijmp(2, B, B)],
+            mem: vec![false; 3],
+            ..State::default()
+        };
+        let svg = p.to_svg();
+        assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>\n"));
+        for label in [
+            "start blue",
+            "0: ijmp 0",
+            "1: ijmp 0",
+            "2: ijmp 2",
+            ">B<",
+            ">R<",
+            ">INTERC0<",
+        ] {
+            assert!(svg.contains(label), "{}", label);
+        }
+        // red is released, and falls off the board
+        assert!(svg.contains("start red") && svg.contains(">FALL<"));
+        assert!(svg.contains(">0,1<"));
+        // instructions 0 and 1 are gear bits
+        assert_eq!(svg.matches(GEAR_COLORS[0]).count(), 2);
+        assert_eq!(svg.matches("stroke-dasharray").count(), 1);
+    }
+
+    #[test]
+    fn board() {
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, B, B)],
+            mem: vec![false; 2],
+            ..State::default()
+        };
+        let board = p.to_board().unwrap();
+        let svg = board.to_svg();
+        assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>\n"));
+        // a peg for each empty cell
+        let empty = board.rows() * board.cols() - board.parts().count();
+        assert_eq!(svg.matches(r#" r="2" "#).count(), empty);
+    }
+}
@@ -0,0 +1,67 @@
+//! Fixtures shared by the tests of the emulators.
+
+use super::*;
+
+/// Balls, start button and value of register `0..2` to run a program with.
+pub(crate) type Setup = ([u8; 2], Color, u64);
+
+/// Check that `run` gives the same results as `State::try_run` on a set of
+/// small programs: jumps to every kind of address, entrypoints that skip
+/// instructions or fall off, and setups with and without balls left.
+/// `run` is given a program and the setups to run it with, and returns the
+/// outcome of each setup.
+pub(crate) fn same_as_try_run<F>(run: F)
+where
+    F: Fn(&State, &[Setup]) -> Vec<Result<State, RunError>>,
+{
+    let mut setups = vec![];
+    for balls in [[0, 0], [1, 0], [3, 2], [0, 5]] {
+        for start in [Color::Blue, Color::Red] {
+            for mem in 0..4 {
+                setups.push((balls, start, mem));
+            }
+        }
+    }
+    let addrs = [
+        0, 1, 2, BLUE_LEVER, RED_LEVER, INTERC1, FALL, UNDECIDED, 200,
+    ];
+    for &e0 in &[0, 1, BLUE_LEVER, FALL, UNDECIDED] {
+        for &e1 in &[0, RED_LEVER, FALL, UNDECIDED] {
+            for &mem1 in &[0, 1, 2] {
+                for &a in &addrs {
+                    for &b in &addrs {
+                        for &c in &[RED_LEVER, INTERC0, 0] {
+                            let p = State {
+                                entry: [e0, e1],
+                                instr: vec![ijmp(0, a, b), ijmp(mem1, c, BLUE_LEVER)],
+                                mem: vec![false; 2],
+                                ..State::default()
+                            };
+                            let out = run(&p, &setups);
+                            assert_eq!(out.len(), setups.len());
+                            for (got, &(balls, start, mem)) in out.into_iter().zip(&setups) {
+                                let want = p
+                                    .with_balls(balls)
+                                    .with_start(start)
+                                    .with_register(0..2, mem)
+                                    .try_run();
+                                match (got, want) {
+                                    (Ok(got), Ok(want)) => {
+                                        assert_eq!(got.to_string(), want.to_string());
+                                        assert_eq!(got.balls, want.balls);
+                                        assert_eq!(got.out_seq, want.out_seq);
+                                        assert_eq!(got.intercept, want.intercept);
+                                        assert_eq!(got.undecided, want.undecided);
+                                    }
+                                    (got, want) => {
+                                        assert_eq!(got.unwrap_err(), want.unwrap_err())
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
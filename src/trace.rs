@@ -0,0 +1,284 @@
+use super::*;
+use std::fmt;
+
+/// Something that happened during a run, see `State::run_traced`.
+/// `ball` is the index of the ball involved, in order of release: the
+/// length of the output sequence when it was released (0: the ball released
+/// by the start button, unless the run continues an earlier one).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// A ball was released, and enters the board at `entry`.
+    Released {
+        ball: usize,
+        color: Color,
+        entry: Addr,
+    },
+    /// The instruction at `pc` flipped `mem` from `old` to `new`,
+    /// and jumped to `jmp`.
+    Executed {
+        ball: usize,
+        pc: Addr,
+        mem: Addr,
+        old: bool,
+        new: bool,
+        jmp: Addr,
+    },
+    /// The ball reached a lever (`BLUE_LEVER` or `RED_LEVER`),
+    /// and was added to the output sequence.
+    Lever {
+        ball: usize,
+        color: Color,
+        lever: Addr,
+    },
+    /// The ball was caught by an interceptor, which halts the machine.
+    Intercepted {
+        ball: usize,
+        color: Color,
+        interceptor: Addr,
+    },
+}
+
+impl TraceEvent {
+    pub fn ball(&self) -> usize {
+        match *self {
+            TraceEvent::Released { ball, .. }
+            | TraceEvent::Executed { ball, .. }
+            | TraceEvent::Lever { ball, .. }
+            | TraceEvent::Intercepted { ball, .. } => ball,
+        }
+    }
+}
+
+/// The events of a run, in order.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct Trace {
+    pub events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    /// The instructions that ball `ball` passed through, in order.
+    pub fn path(&self, ball: usize) -> Vec<Addr> {
+        self.events
+            .iter()
+            .filter_map(|e| match *e {
+                TraceEvent::Executed { ball: b, pc, .. } if b == ball => Some(pc),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Did ball `ball` pass through the instruction at `pc`?
+    pub fn passed(&self, ball: usize, pc: Addr) -> bool {
+        self.path(ball).contains(&pc)
+    }
+
+    /// Number of balls released.
+    pub fn balls(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|e| matches!(e, TraceEvent::Released { .. }))
+            .count()
+    }
+}
+
+/// Where `State::exec` records events.
+pub(crate) trait Tracer {
+    fn push(&mut self, event: TraceEvent);
+}
+
+/// Records nothing.
+impl Tracer for () {
+    #[inline(always)]
+    fn push(&mut self, _: TraceEvent) {}
+}
+
+impl Tracer for Vec<TraceEvent> {
+    fn push(&mut self, event: TraceEvent) {
+        Vec::push(self, event)
+    }
+}
+
+impl State {
+    /// Like `run`, but also records what happened, e.g. to visualize the run,
+    /// or to check which instructions a ball passed through.
+    pub fn run_traced(mut self) -> (Self, Trace) {
+        let mut trace = Trace::default();
+        if let Err(err) = self.exec(0, false, &mut trace.events) {
+            panic!("{}", err)
+        }
+        (self, trace)
+    }
+
+    /// Like `run_traced`, but returns an error instead of panicking, see
+    /// `try_run`.
+    pub fn try_run_traced(mut self) -> Result<(Self, Trace), RunError> {
+        let mut trace = Trace::default();
+        self.exec(0, true, &mut trace.events)?;
+        Ok((self, trace))
+    }
+}
+
+/// E.g. `ball 2: PC 1: mem[1]: 0 -> 1, jmp B`
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ball {}: ", self.ball())?;
+        match *self {
+            TraceEvent::Released { color, entry, .. } => {
+                write!(f, "{} released, enters at ", color)?;
+                fmt_addr(entry, f)
+            }
+            TraceEvent::Executed {
+                pc,
+                mem,
+                old,
+                new,
+                jmp,
+                ..
+            } => {
+                write!(
+                    f,
+                    "PC {}: mem[{}]: {} -> {}, jmp ",
+                    pc, mem, old as u8, new as u8
+                )?;
+                fmt_addr(jmp, f)
+            }
+            TraceEvent::Lever { color, lever, .. } => {
+                write!(f, "{} reaches lever ", color)?;
+                fmt_addr(lever, f)
+            }
+            TraceEvent::Intercepted {
+                color, interceptor, ..
+            } => {
+                write!(f, "{} intercepted by ", color)?;
+                fmt_addr(interceptor, f)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use Color::*;
+
+    const B: Addr = BLUE_LEVER;
+
+    #[test]
+    fn trace_counter() {
+        let counter = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, B, B)],
+            mem: vec![false; 2],
+            balls: [3, 0],
+            ..State::default()
+        };
+        let (out, trace) = counter.clone().run_traced();
+        assert_eq!(out.register(0..2), 3);
+        assert_eq!(trace.balls(), 3);
+        assert_eq!(trace.path(0), vec![0]);
+        assert_eq!(trace.path(1), vec![0, 1]);
+        assert!(trace.passed(2, 0) && !trace.passed(2, 1));
+        assert_eq!(
+            trace.events[..4],
+            [
+                TraceEvent::Released {
+                    ball: 0,
+                    color: Blue,
+                    entry: 0
+                },
+                TraceEvent::Executed {
+                    ball: 0,
+                    pc: 0,
+                    mem: 0,
+                    old: false,
+                    new: true,
+                    jmp: B
+                },
+                TraceEvent::Lever {
+                    ball: 0,
+                    color: Blue,
+                    lever: B
+                },
+                TraceEvent::Released {
+                    ball: 1,
+                    color: Blue,
+                    entry: 0
+                },
+            ]
+        );
+        assert_eq!(
+            trace.events[5].to_string(),
+            "ball 1: PC 1: mem[1]: 0 -> 1, jmp B"
+        );
+        assert_eq!(out.to_string(), counter.run().to_string());
+    }
+
+    #[test]
+    fn trace_continued() {
+        let counter = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, B, B)],
+            mem: vec![false; 2],
+            balls: [2, 0],
+            ..State::default()
+        };
+        // balls 0 and 1 were output by the first run
+        let (out, trace) = counter.run().with_balls([1, 0]).run_traced();
+        assert_eq!(out.register(0..2), 3);
+        assert_eq!(trace.events[0].ball(), 2);
+        assert_eq!(trace.path(2), vec![0]);
+    }
+
+    #[test]
+    fn trace_intercept() {
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, INTERC0, B)],
+            mem: vec![true],
+            balls: [3, 0],
+            ..State::default()
+        };
+        let (_, trace) = p.clone().run_traced();
+        assert_eq!(
+            trace.to_string(),
+            "ball 0: b released, enters at 0\n\
+             ball 0: PC 0: mem[0]: 1 -> 0, jmp INTERC0\n\
+             ball 0: b intercepted by INTERC0\n"
+        );
+        assert_eq!(p.try_run_traced().unwrap().1, trace);
+    }
+
+    #[test]
+    fn try_run_traced() {
+        let backward = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, B, 0)],
+            mem: vec![false],
+            balls: [3, 0],
+            ..State::default()
+        };
+        assert_eq!(
+            backward.clone().try_run_traced().unwrap_err(),
+            RunError::BackwardJump {
+                pc: 0,
+                target: 0,
+                ball: 0
+            }
+        );
+        let falls = backward.with_entry([FALL, FALL]);
+        assert_eq!(
+            falls.try_run_traced().unwrap_err(),
+            RunError::FellOff { pc: FALL, ball: 0 }
+        );
+    }
+}
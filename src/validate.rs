@@ -0,0 +1,242 @@
+use super::*;
+use std::fmt;
+
+/// A problem found by `State::validate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The part of the program the problem is about.
+    pub subject: Subject,
+    /// E.g. "is unreachable", "jumps backwards to 2".
+    pub msg: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The program will (or may) fail to run, see `RunError`.
+    Error,
+    /// The program runs, but probably not as intended.
+    Warning,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subject {
+    /// The entrypoint for balls of this color.
+    Entry(Color),
+    Instr(Addr),
+    Mem(Addr),
+}
+
+impl Diagnostic {
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl State {
+    /// Check the program without running it. Errors:
+    /// - jumps that do not go strictly forward, which is what guarantees
+    ///   that every run terminates
+    /// - jumps and entrypoints to instructions or addresses that do not exist
+    /// - memory addresses that do not exist
+    /// - a released ball that falls off the board
+    ///
+    /// Warnings: jumps to `FALL` or `UNDECIDED` in reachable instructions,
+    /// unreachable instructions, and memory that no instruction reads.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diags = vec![];
+        let mut diag = |severity, subject, msg: String| {
+            diags.push(Diagnostic {
+                severity,
+                subject,
+                msg,
+            })
+        };
+        let n_instr = self.instr.len();
+        let (released, reachable) = self.reachable();
+
+        for color in [Color::Blue, Color::Red] {
+            let entry = self.entry[usize::from(color)];
+            if !released[usize::from(color)] {
+                continue;
+            }
+            let subject = Subject::Entry(color);
+            match entry {
+                addr if (addr as usize) < n_instr => (),
+                addr if addr < BLUE_LEVER => diag(
+                    Severity::Error,
+                    subject,
+                    format!("goes to instruction {}, past the end of the program", addr),
+                ),
+                BLUE_LEVER..=INTERC2 => (),
+                FALL => diag(Severity::Error, subject, "falls off the board".into()),
+                UNDECIDED => diag(Severity::Warning, subject, "is undecided".into()),
+                addr => diag(
+                    Severity::Error,
+                    subject,
+                    format!("goes to invalid address {}", addr),
+                ),
+            }
+        }
+
+        for (i, instr) in self.instr.iter().enumerate() {
+            let subject = Subject::Instr(i as Addr);
+            if !reachable[i] {
+                diag(Severity::Warning, subject, "is unreachable".into());
+            }
+            if instr.mem as usize >= self.mem.len() {
+                diag(
+                    Severity::Error,
+                    subject,
+                    format!("uses mem[{}], past the end of memory", instr.mem),
+                );
+            }
+            for (name, jmp) in [("jmp0", instr.jmp0), ("jmp1", instr.jmp1)] {
+                match jmp {
+                    addr if addr < BLUE_LEVER && (addr as usize) <= i => diag(
+                        Severity::Error,
+                        subject,
+                        format!("{} jumps backwards to {}", name, addr),
+                    ),
+                    addr if addr < BLUE_LEVER && (addr as usize) >= n_instr => diag(
+                        Severity::Error,
+                        subject,
+                        format!(
+                            "{} goes to instruction {}, past the end of the program",
+                            name, addr
+                        ),
+                    ),
+                    addr if addr < BLUE_LEVER => (),
+                    BLUE_LEVER..=INTERC2 => (),
+                    FALL if reachable[i] => diag(
+                        Severity::Warning,
+                        subject,
+                        format!("{} falls off the board", name),
+                    ),
+                    UNDECIDED if reachable[i] => {
+                        diag(Severity::Warning, subject, format!("{} is undecided", name))
+                    }
+                    FALL | UNDECIDED => (),
+                    addr => diag(
+                        Severity::Error,
+                        subject,
+                        format!("{} goes to invalid address {}", name, addr),
+                    ),
+                }
+            }
+        }
+
+        for m in 0..self.mem.len() {
+            if !self.instr.iter().any(|instr| instr.mem as usize == m) {
+                diag(
+                    Severity::Warning,
+                    Subject::Mem(m as Addr),
+                    "is never read".into(),
+                );
+            }
+        }
+        diags
+    }
+
+    /// Which ball colors may be released, and which instructions may be
+    /// reached by them: starting from the start button, and following all
+    /// jumps, also backwards ones.
+    pub(crate) fn reachable(&self) -> ([bool; 2], Vec<bool>) {
+        let mut released = [false; 2];
+        let mut reachable = vec![false; self.instr.len()];
+        let mut todo = vec![];
+        let release = |color: Color, released: &mut [bool; 2], todo: &mut Vec<Addr>| {
+            if !released[usize::from(color)] {
+                released[usize::from(color)] = true;
+                todo.push(self.entry[usize::from(color)]);
+            }
+        };
+        release(self.start_button, &mut released, &mut todo);
+
+        while let Some(addr) = todo.pop() {
+            match addr {
+                BLUE_LEVER => release(Color::Blue, &mut released, &mut todo),
+                RED_LEVER => release(Color::Red, &mut released, &mut todo),
+                addr if (addr as usize) < self.instr.len() && !reachable[addr as usize] => {
+                    reachable[addr as usize] = true;
+                    let instr = self.instr[addr as usize];
+                    todo.extend([instr.jmp0, instr.jmp1]);
+                }
+                _ => (),
+            }
+        }
+        (released, reachable)
+    }
+}
+
+/// E.g. `warning: instruction 4 is unreachable`
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {} {}", severity, self.subject, self.msg)
+    }
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Subject::Entry(Color::Blue) => write!(f, "start_blue"),
+            Subject::Entry(Color::Red) => write!(f, "start_red"),
+            Subject::Instr(i) => write!(f, "instruction {}", i),
+            Subject::Mem(m) => write!(f, "mem[{}]", m),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const B: Addr = BLUE_LEVER;
+
+    fn messages(p: &State) -> Vec<String> {
+        p.validate().iter().map(Diagnostic::to_string).collect()
+    }
+
+    #[test]
+    fn valid() {
+        let counter = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, B), ijmp(1, B, B)],
+            mem: vec![false; 2],
+            ..State::default()
+        };
+        assert!(counter.validate().is_empty());
+    }
+
+    #[test]
+    fn diagnostics() {
+        let p = State {
+            entry: [0, FALL],
+            instr: vec![ijmp(0, 1, RED_LEVER), ijmp(3, 0, FALL), ijmp(1, 7, 200)],
+            mem: vec![false; 3],
+            ..State::default()
+        };
+        assert_eq!(
+            messages(&p),
+            vec![
+                "error: start_red falls off the board",
+                "error: instruction 1 uses mem[3], past the end of memory",
+                "error: instruction 1 jmp0 jumps backwards to 0",
+                "warning: instruction 1 jmp1 falls off the board",
+                "warning: instruction 2 is unreachable",
+                "error: instruction 2 jmp0 goes to instruction 7, past the end of the program",
+                "error: instruction 2 jmp1 goes to invalid address 200",
+                "warning: mem[2] is never read",
+            ]
+        );
+        assert_eq!(p.validate().iter().filter(|d| d.is_error()).count(), 5);
+
+        let p = p.with_start(Color::Red);
+        assert_eq!(messages(&p)[0], "error: start_red falls off the board");
+    }
+}
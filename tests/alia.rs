@@ -0,0 +1,29 @@
+use magnus::*;
+use std::process::Command;
+
+// The programs printed by `alia --board` still solve the puzzle.
+#[test]
+fn printed_solutions_pass() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/puzzles/set_reset.txt");
+    let file: PuzzleFile = std::fs::read_to_string(path).unwrap().parse().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_alia"))
+        .args(["--board", path])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    let mut n = 0;
+    for block in stdout.split("===================\n") {
+        if !block.starts_with("solution") {
+            continue;
+        }
+        let src = block.split_once('\n').unwrap().1;
+        let p: State = src.parse().unwrap();
+        assert!(file.puzzle.check(&p), "{}", block);
+        n += 1;
+    }
+    assert!(n > 1);
+    let boards = stdout.lines().filter(|l| l.starts_with("board")).count();
+    assert_eq!(boards, n);
+}